@@ -1,57 +1,798 @@
 use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList};
+use pyo3::types::{PyBytes, PyDict, PyList};
 use rayon::prelude::*;
+use rustpython_parser::source_code::LineIndex;
+use rustpython_parser::text_size::{TextRange, TextSize};
 use rustpython_parser::{ast, Parse};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::RwLock;
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, RwLock};
+use std::time::{Instant, SystemTime};
+use unicode_normalization::UnicodeNormalization;
 use walkdir::WalkDir;
 
 // PHASE 3: Rust-side caching constants
-const CACHE_VERSION: &str = "1.0";
+const CACHE_VERSION: &str = "1.26";
 const MTIME_TOLERANCE_SECONDS: f64 = 0.01;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Markers pytest always knows about, registered or not
+const BUILTIN_MARKERS: &[&str] = &["skip", "skipif", "xfail", "parametrize", "usefixtures", "filterwarnings"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct TestItem {
     file_path: String,
     name: String,
+    /// Python-style `__qualname__`, e.g. `TestAPI.TestGet.test_status`
+    #[serde(default)]
+    qualname: String,
+    /// 1-indexed line of the `def`/`class` keyword
     line_number: usize,
+    /// 1-indexed last line of the definition body
+    #[serde(default)]
+    end_line: usize,
     item_type: TestItemType,
+    /// Defined with `async def` (pytest-asyncio / anyio style)
+    #[serde(default)]
+    is_async: bool,
     class_name: Option<String>,
     markers: Vec<String>,
+    /// Markers applied by the enclosing class or module (`pytestmark`, class decorators)
+    #[serde(default)]
+    inherited_markers: Vec<String>,
     /// Parametrize info: list of parameter sets (for generating correct number of test nodes)
     parametrize_count: Option<usize>,
+    /// Parametrize passes values through fixtures (`indirect=True` or a list of names),
+    /// which changes the generated node ids
+    #[serde(default)]
+    parametrize_indirect: bool,
+    /// Individual cases of a literal parametrize, in order (see `case_items`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    parametrize_cases: Vec<ParamCase>,
+    /// Fixtures requested through arguments and `@pytest.mark.usefixtures`
+    #[serde(default)]
+    requested_fixtures: Vec<String>,
+    /// Literal `reason` of a `skip`/`skipif` marker on the test, its class or its module
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    skip_reason: Option<String>,
+    /// Seconds from `@pytest.mark.timeout(30)` (pytest-timeout), when given as a literal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout: Option<f64>,
+    /// Byte offsets of the definition in the file, captured with `set_capture_offsets(true)`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    byte_start: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    byte_end: Option<usize>,
+    /// Case id of an item expanded from a parametrized test (`set_expand_parametrize(true)`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parametrize_id: Option<String>,
+    /// Line-independent key (`path::qualname[case]`) for persisting order, with `set_order_keys(true)`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    order_key: Option<String>,
+    /// `test_foo(self, db, client)`, captured with `set_capture_signature(true)`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    signature: String,
+    /// Number of `assert` statements in the body (only counted with `set_count_asserts(true)`)
+    #[serde(default)]
+    assert_count: usize,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// One case of a parametrized test: its pytest id and the marks from `pytest.param(..., marks=...)`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ParamCase {
+    id: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    marks: Vec<String>,
+}
+
+/// Flat, schema-stable item record: `(file, name, class, line, type, markers)`
+type ItemRecord = (String, String, Option<String>, usize, String, Vec<String>);
+
+impl TestItem {
+    /// Flat record with the same values as the dict output (`file_path`, `name`, `class`, `line`, `type`, `markers`)
+    fn to_record(&self) -> ItemRecord {
+        (
+            self.file_path.clone(),
+            self.name.clone(),
+            self.class_name.clone(),
+            self.line_number,
+            format!("{:?}", self.item_type),
+            self.markers.clone(),
+        )
+    }
+
+    /// One item per parametrized case (`test_x[id]`), each carrying its own case marks
+    fn case_items(&self) -> Vec<TestItem> {
+        self.parametrize_cases
+            .iter()
+            .map(|case| {
+                let mut item = self.clone();
+                item.name = format!("{}[{}]", self.name, case.id);
+                item.markers.extend(case.marks.iter().cloned());
+                item.parametrize_count = None;
+                item.parametrize_cases = Vec::new();
+                item.parametrize_id = Some(case.id.clone());
+                item
+            })
+            .collect()
+    }
+
+    /// One item per case when the case ids are statically known, otherwise the item itself
+    /// Stacked or class-level parametrizations get ids pytest composes at runtime
+    /// (`test_x[3-1]`), so such items stay unexpanded with their `parametrize_count`.
+    fn expanded(self) -> Vec<TestItem> {
+        if self.parametrize_cases.is_empty() {
+            return vec![self];
+        }
+        self.case_items()
+    }
+
+    /// Own markers plus the ones inherited from the class and module, like pytest's `iter_markers()`
+    fn effective_markers(&self) -> impl Iterator<Item = &String> {
+        self.markers.iter().chain(self.inherited_markers.iter())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 enum TestItemType {
     Function,
     Class,
     Method,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct FileMetadata {
     path: String,
     mtime: f64,
     test_items: Vec<TestItem>,
+    /// Module passed to a top-level `pytest.importorskip(...)` (informational)
+    #[serde(default)]
+    conditional_skip_module: Option<String>,
+    /// `conftest.py` files found by `scan_conftest` that apply to this file, nearest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    applicable_conftests: Vec<String>,
+    /// Syntax features requiring a minimum Python version, e.g. `match` (3.10)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    syntax_features: Vec<String>,
+    /// Marks from the module-level `pytestmark`, applying to every test in the file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    module_markers: Vec<String>,
+    /// Modules imported at module level, sorted (`from a import b` records `a` and `a.b`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    imports: Vec<String>,
+}
+
+impl FileMetadata {
+    fn new(path: String, mtime: f64, parsed: ParsedFile) -> Self {
+        FileMetadata {
+            path,
+            mtime,
+            test_items: parsed.items,
+            conditional_skip_module: parsed.conditional_skip_module,
+            applicable_conftests: Vec::new(),
+            syntax_features: parsed.syntax_features,
+            module_markers: parsed.module_markers,
+            imports: parsed.imports,
+        }
+    }
+}
+
+/// Everything extracted from one source file: test items plus file-level facts
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct ParsedFile {
+    items: Vec<TestItem>,
+    #[serde(default)]
+    conditional_skip_module: Option<String>,
+    /// Set when the file could not be parsed (cached so the failure is reported on every run)
+    #[serde(default)]
+    parse_error: Option<FileParseError>,
+    /// Version-gated syntax used by the file (`set_detect_syntax_features(true)`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    syntax_features: Vec<String>,
+    /// Marks from the module-level `pytestmark`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    module_markers: Vec<String>,
+    /// Absolute imports at module level
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    imports: Vec<String>,
+    /// Warnings found while extracting items, e.g. cyclic inheritance (cached so they are
+    /// reported on every run)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+/// Syntax error found while parsing a test file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct FileParseError {
+    file_path: String,
+    message: String,
+    line: usize,
+    column: usize,
+}
+
+impl std::fmt::Display for FileParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file_path, self.line, self.column, self.message)
+    }
+}
+
+/// Result of one file sent from the parsing threads by `collect_streaming`
+enum StreamEvent {
+    File(FileMetadata),
+    ParseError(FileParseError),
+}
+
+/// What to do when a test file has a syntax error
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParseErrorPolicy {
+    /// Silently drop the file (historical behavior)
+    Skip,
+    /// Drop the file but record the error and a warning
+    Warn,
+    /// Abort collection with a `SyntaxError`
+    Error,
+}
+
+impl ParseErrorPolicy {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "skip" => Some(ParseErrorPolicy::Skip),
+            "warn" => Some(ParseErrorPolicy::Warn),
+            "error" => Some(ParseErrorPolicy::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Settings read from `.fastcollect.toml` in the root; absent keys keep their current value
+#[derive(Debug, Default, Deserialize)]
+struct FastcollectConfig {
+    test_patterns: Option<Vec<String>>,
+    ignore_patterns: Option<Vec<String>>,
+    function_prefixes: Option<Vec<String>>,
+    class_prefixes: Option<Vec<String>>,
+    cache_path: Option<PathBuf>,
+}
+
+/// Keys `.fastcollect.toml` understands; others are ignored unless `set_strict_config(true)`
+const FASTCOLLECT_CONFIG_KEYS: [&str; 5] =
+    ["test_patterns", "ignore_patterns", "function_prefixes", "class_prefixes", "cache_path"];
+
+/// Hasher used for content-based cache validation
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HashAlgorithm {
+    Blake3,
+    Xxhash,
+}
+
+impl HashAlgorithm {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "blake3" => Some(HashAlgorithm::Blake3),
+            "xxhash" => Some(HashAlgorithm::Xxhash),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Xxhash => "xxhash",
+        }
+    }
+
+    /// Digest prefixed with the algorithm name (`blake3:<hex>`), so hashes from
+    /// another algorithm never compare equal
+    fn digest(&self, content: &[u8]) -> String {
+        let hex = match self {
+            HashAlgorithm::Blake3 => blake3::hash(content).to_hex().to_string(),
+            HashAlgorithm::Xxhash => format!("{:016x}", xxhash_rust::xxh3::xxh3_64(content)),
+        };
+        format!("{}:{}", self.name(), hex)
+    }
 }
 
 /// PHASE 3: Cache entry for storing parsed test data with modification time
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct CacheEntry {
     mtime: f64,
-    items: Vec<TestItem>,
+    /// Content digest, present when a hash algorithm is configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_hash: Option<String>,
+    #[serde(flatten)]
+    parsed: ParsedFile,
 }
 
 /// PHASE 3: Cache structure for persistence
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct CacheData {
     version: String,
-    entries: HashMap<String, CacheEntry>,
+    /// Parse options the entries were produced with (see `parse_options_key`)
+    #[serde(default)]
+    options: String,
+    /// Sorted so unchanged data always serializes to the same bytes
+    entries: BTreeMap<String, CacheEntry>,
+    /// Directory listings for the incremental walk, keyed by directory path
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    directories: BTreeMap<String, DirListing>,
+}
+
+/// Contents of one directory as of its mtime; reused while the mtime is unchanged
+/// (adding, removing or renaming an entry updates the directory's mtime)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DirListing {
+    mtime: f64,
+    files: Vec<String>,
+    dirs: Vec<String>,
+}
+
+/// State inherited from enclosing module and class bodies during extraction
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    class_name: Option<String>,
+    markers: Vec<String>,
+    usefixtures: Vec<String>,
+    /// Parametrize applied by enclosing classes: case count multiplier and argument names
+    parametrize_count: Option<usize>,
+    parametrize_argnames: Vec<String>,
+    skip_reason: Option<String>,
+}
+
+/// Write through a temporary file and rename it, so readers never see a half-written file
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let temp_path = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path)
+}
+
+/// Whether an `if` test is `__name__ == "__main__"` (either operand order)
+fn is_main_guard(test: &ast::Expr) -> bool {
+    let ast::Expr::Compare(compare) = test else {
+        return false;
+    };
+    let (Some(right), [ast::CmpOp::Eq]) = (compare.comparators.first(), &compare.ops[..]) else {
+        return false;
+    };
+    let is_name = |expr: &ast::Expr| matches!(expr, ast::Expr::Name(name) if name.id.as_str() == "__name__");
+    let is_main = |expr: &ast::Expr| {
+        matches!(expr, ast::Expr::Constant(c) if c.value.as_str().map(|s| s.as_str()) == Some("__main__"))
+    };
+    (is_name(&compare.left) && is_main(right)) || (is_main(&compare.left) && is_name(right))
+}
+
+/// The parts of a `def` or `async def` statement that extraction looks at
+struct FunctionDef<'a> {
+    name: &'a str,
+    args: &'a ast::Arguments,
+    body: &'a [ast::Stmt],
+    decorator_list: &'a [ast::Expr],
+    range: TextRange,
+    is_async: bool,
+}
+
+impl<'a> FunctionDef<'a> {
+    fn from_stmt(stmt: &'a ast::Stmt) -> Option<Self> {
+        match stmt {
+            ast::Stmt::FunctionDef(func) => Some(FunctionDef {
+                name: func.name.as_str(),
+                args: &func.args,
+                body: &func.body,
+                decorator_list: &func.decorator_list,
+                range: func.range,
+                is_async: false,
+            }),
+            ast::Stmt::AsyncFunctionDef(func) => Some(FunctionDef {
+                name: func.name.as_str(),
+                args: &func.args,
+                body: &func.body,
+                decorator_list: &func.decorator_list,
+                range: func.range,
+                is_async: true,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Source text of the file being extracted, for mapping offsets to lines
+struct SourceFile<'a> {
+    path: &'a str,
+    content: &'a str,
+    line_index: LineIndex,
+    /// Module-level helpers returning a marker, e.g. `def mark_slow(): return pytest.mark.slow`
+    marker_factories: HashMap<String, String>,
+    /// Module-level names bound to the mark namespace, e.g. `m = pytest.mark`
+    mark_aliases: HashSet<String>,
+    /// Module-level classes by name, for resolving base classes
+    classes: HashMap<String, &'a ast::StmtClassDef>,
+    /// Problems found while extracting, kept on the `ParsedFile`
+    warnings: RefCell<Vec<String>>,
+}
+
+impl<'a> SourceFile<'a> {
+    fn new(path: &'a str, content: &'a str) -> Self {
+        SourceFile {
+            path,
+            content,
+            line_index: LineIndex::from_source_text(content),
+            marker_factories: HashMap::new(),
+            mark_aliases: HashSet::new(),
+            classes: HashMap::new(),
+            warnings: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// 1-indexed line containing the given offset
+    fn line(&self, offset: TextSize) -> usize {
+        self.line_index.source_location(offset, self.content).row.to_usize()
+    }
+}
+
+/// Marker name to the node ids carrying it
+type MarkerIndex = BTreeMap<String, Vec<String>>;
+
+/// Records the version-gated syntax of a module while folding it unchanged
+/// Features: `async` (3.5), `fstring` (3.6), `walrus` (3.8), `match` (3.10),
+/// `except_star` (3.11) and `type_params` (3.12)
+#[derive(Default)]
+struct SyntaxFeatures {
+    found: BTreeSet<&'static str>,
+}
+
+impl ast::Fold<TextRange> for SyntaxFeatures {
+    type TargetU = TextRange;
+    type Error = std::convert::Infallible;
+    type UserContext = ();
+
+    fn will_map_user(&mut self, _user: &TextRange) {}
+
+    fn map_user(&mut self, user: TextRange, _context: ()) -> Result<TextRange, Self::Error> {
+        Ok(user)
+    }
+
+    fn fold_stmt(&mut self, node: ast::Stmt) -> Result<ast::Stmt, Self::Error> {
+        match node {
+            ast::Stmt::AsyncFunctionDef(_) | ast::Stmt::AsyncFor(_) | ast::Stmt::AsyncWith(_) => {
+                self.found.insert("async");
+            }
+            ast::Stmt::Match(_) => {
+                self.found.insert("match");
+            }
+            ast::Stmt::TryStar(_) => {
+                self.found.insert("except_star");
+            }
+            ast::Stmt::TypeAlias(_) => {
+                self.found.insert("type_params");
+            }
+            _ => {}
+        }
+        ast::fold::fold_stmt(self, node)
+    }
+
+    fn fold_expr(&mut self, node: ast::Expr) -> Result<ast::Expr, Self::Error> {
+        match node {
+            ast::Expr::Await(_) => {
+                self.found.insert("async");
+            }
+            ast::Expr::JoinedStr(_) => {
+                self.found.insert("fstring");
+            }
+            ast::Expr::NamedExpr(_) => {
+                self.found.insert("walrus");
+            }
+            _ => {}
+        }
+        ast::fold::fold_expr(self, node)
+    }
+
+    fn fold_type_param(&mut self, node: ast::TypeParam) -> Result<ast::TypeParam, Self::Error> {
+        self.found.insert("type_params");
+        ast::fold::fold_type_param(self, node)
+    }
+}
+
+/// `(keyword_expr, marker_expr)` of a filter
+type FilterKey = (Option<String>, Option<String>);
+
+/// Python `__qualname__` of a definition inside the given class path (`Outer::Inner`)
+fn qualname(class_path: Option<&str>, name: &str) -> String {
+    match class_path {
+        Some(class_path) => format!("{}.{}", class_path.replace("::", "."), name),
+        None => name.to_string(),
+    }
+}
+
+/// Simple wildcard matching (supports * anywhere in pattern)
+fn matches_wildcard(text: &str, pattern: &str) -> bool {
+    // Split pattern by '*'
+    let parts: Vec<&str> = pattern.split('*').collect();
+
+    if parts.len() == 1 {
+        // No wildcards, exact match
+        return text == pattern;
+    }
+
+    let mut current_pos = 0;
+
+    for (i, part) in parts.iter().enumerate() {
+        if part.is_empty() {
+            continue;
+        }
+
+        if i == 0 {
+            // First part must match at start
+            if !text.starts_with(part) {
+                return false;
+            }
+            current_pos = part.len();
+        } else if i == parts.len() - 1 {
+            // Last part must match at end
+            if !text.ends_with(part) {
+                return false;
+            }
+            // Check that we haven't gone past the end
+            if current_pos > text.len() - part.len() {
+                return false;
+            }
+        } else {
+            // Middle parts can match anywhere after current position
+            if let Some(pos) = text[current_pos..].find(part) {
+                current_pos += pos + part.len();
+            } else {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+/// Match a `/`-separated path against a glob: `*` stays within one segment, a `**` segment
+/// spans any number of segments (including none)
+fn matches_path_glob(path: &str, pattern: &str) -> bool {
+    fn matches_segments(path: &[&str], pattern: &[&str]) -> bool {
+        match pattern.split_first() {
+            None => path.is_empty(),
+            Some((&"**", rest)) => (0..=path.len()).any(|skip| matches_segments(&path[skip..], rest)),
+            Some((segment, rest)) => match path.split_first() {
+                Some((first, path_rest)) => matches_wildcard(first, segment) && matches_segments(path_rest, rest),
+                None => false,
+            },
+        }
+    }
+    let path: Vec<&str> = path.split('/').collect();
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    matches_segments(&path, &pattern)
+}
+
+/// A test file read from a git tree by `set_git_ref` collection
+#[cfg(feature = "git")]
+struct GitSource {
+    /// Path inside the repository, used for node ids
+    tree_path: String,
+    /// Path relative to the rootdir (or root), matched against the include/exclude globs
+    relative_path: String,
+    content: String,
+}
+
+/// Drop paths that canonicalize to an already seen file, keeping the first spelling
+fn dedupe_paths(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    paths
+        .into_iter()
+        .filter(|path| seen.insert(fs::canonicalize(path).unwrap_or_else(|_| path.clone())))
+        .collect()
+}
+
+/// Quote a string as a DOT identifier
+fn dot_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Escape text for an XML attribute value
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Normalize an identifier the way the Python parser does (NFKC)
+fn normalize_identifier(name: &str) -> String {
+    name.nfkc().collect()
+}
+
+/// Case-insensitive comparison key for keyword and marker matching
+/// NFKC first so that composed and decomposed spellings of a name compare equal
+fn fold_case(text: &str) -> String {
+    normalize_identifier(text).to_lowercase()
+}
+
+/// Serialize a collection result for Python, raising `RuntimeError` on failure
+fn to_json<T: Serialize + ?Sized>(value: &T) -> PyResult<String> {
+    serde_json::to_string(value)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("JSON serialization failed: {}", e)))
+}
+
+/// File modification time in seconds since the epoch (0.0 when unavailable)
+fn file_mtime(path: &Path) -> f64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+/// Python callable wrapped by `set_node_id_rewriter`
+type NodeIdRewriter = Box<dyn Fn(String) -> PyResult<String> + Send + Sync>;
+
+/// Map each node id through `rewrite`, stopping at the first error
+fn rewrite_node_ids<E, F>(node_ids: Vec<String>, rewrite: F) -> Result<Vec<String>, E>
+where
+    F: FnMut(String) -> Result<String, E>,
+{
+    node_ids.into_iter().map(rewrite).collect()
+}
+
+/// Deterministic Fisher-Yates shuffle driven by a SplitMix64 generator
+fn shuffle_seeded<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    for i in (1..items.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// Fallback parametrize count named by `set_unknown_parametrize_behavior`: "none" (unknown),
+/// "one", or a count such as "5"
+fn parse_unknown_parametrize_behavior(behavior: &str) -> Result<Option<usize>, String> {
+    match behavior {
+        "none" => Ok(None),
+        "one" => Ok(Some(1)),
+        other => other.parse::<usize>().map(Some).map_err(|_| {
+            format!("Invalid unknown parametrize behavior '{}' (expected 'none', 'one' or a count)", other)
+        }),
+    }
+}
+
+/// Decode Python source: UTF-8 (with or without BOM), UTF-16 announced by a BOM, then
+/// Latin-1 declared by a PEP 263 coding cookie
+fn decode_source(bytes: &[u8]) -> Option<String> {
+    let utf16 = |data: &[u8], decode: fn([u8; 2]) -> u16| -> Option<String> {
+        // A trailing odd byte is a truncated code unit
+        if !data.len().is_multiple_of(2) {
+            return None;
+        }
+        let units: Vec<u16> = data.chunks_exact(2).map(|pair| decode([pair[0], pair[1]])).collect();
+        String::from_utf16(&units).ok()
+    };
+    match bytes {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8(rest.to_vec()).ok(),
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        _ => String::from_utf8(bytes.to_vec()).ok().or_else(|| {
+            // Latin-1 maps every byte to the code point of the same value
+            is_latin1_cookie(&coding_cookie(bytes)?).then(|| bytes.iter().map(|&byte| char::from(byte)).collect())
+        }),
+    }
+}
+
+/// Encoding named by a PEP 263 cookie (`# -*- coding: latin-1 -*-`) on the first line, or on
+/// the second when the first is blank or a comment
+fn coding_cookie(bytes: &[u8]) -> Option<String> {
+    let mut lines = bytes.split(|&byte| byte == b'\n').map(String::from_utf8_lossy);
+    let cookie = |line: &str| -> Option<String> {
+        let (_, rest) = line.trim_start().strip_prefix('#')?.split_once("coding")?;
+        let rest = rest.strip_prefix([':', '='])?.trim_start();
+        let name: String = rest.chars().take_while(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.')).collect();
+        (!name.is_empty()).then_some(name)
+    };
+    let first = lines.next()?;
+    if let Some(name) = cookie(&first) {
+        return Some(name);
+    }
+    let first = first.trim();
+    if !first.is_empty() && !first.starts_with('#') {
+        return None;
+    }
+    cookie(&lines.next()?)
+}
+
+/// Whether a cookie names Latin-1, normalized like CPython's tokenizer (`latin_1`, `ISO-8859-1`, ...)
+fn is_latin1_cookie(name: &str) -> bool {
+    let name = name.to_lowercase().replace('_', "-");
+    ["latin-1", "iso-8859-1", "iso-latin-1"].iter().any(|prefix| name.starts_with(prefix))
+        || ["latin1", "iso8859-1", "l1"].contains(&name.as_str())
+}
+
+/// Serialize file metadata as MessagePack with named fields (decodes to dicts in Python)
+fn encode_msgpack(metadata: &[FileMetadata]) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    rmp_serde::to_vec_named(metadata)
+}
+
+/// Column-oriented view of collected items (one row per item)
+#[derive(Debug, Default)]
+struct ItemColumns {
+    file_path: Vec<String>,
+    name: Vec<String>,
+    class_name: Vec<Option<String>>,
+    line: Vec<usize>,
+    item_type: Vec<String>,
+    markers: Vec<Vec<String>>,
+}
+
+impl ItemColumns {
+    fn from_metadata(metadata: &[FileMetadata]) -> Self {
+        let mut columns = ItemColumns::default();
+        for item in metadata.iter().flat_map(|file_meta| &file_meta.test_items) {
+            columns.file_path.push(item.file_path.clone());
+            columns.name.push(item.name.clone());
+            columns.class_name.push(item.class_name.clone());
+            columns.line.push(item.line_number);
+            columns.item_type.push(format!("{:?}", item.item_type));
+            columns.markers.push(item.markers.clone());
+        }
+        columns
+    }
+}
+
+/// Counters for the last collection
+#[derive(Debug, Default)]
+struct CollectionStats {
+    files_parsed: AtomicUsize,
+    cache_hits: AtomicUsize,
+    /// Directories read from disk (not served from the incremental walk's listings)
+    dirs_listed: AtomicUsize,
+    /// `collect_node_ids` calls answered from the result cache
+    result_cache_hits: AtomicUsize,
+    /// Files and tests (excluding class items) returned by the last filtered collection
+    files_collected: AtomicUsize,
+    tests_collected: AtomicUsize,
+    /// Wall-clock duration of the last filtered collection
+    duration_micros: AtomicUsize,
+}
+
+impl CollectionStats {
+    fn reset(&self) {
+        self.files_parsed.store(0, Ordering::Relaxed);
+        self.cache_hits.store(0, Ordering::Relaxed);
+        self.dirs_listed.store(0, Ordering::Relaxed);
+        self.result_cache_hits.store(0, Ordering::Relaxed);
+        self.files_collected.store(0, Ordering::Relaxed);
+        self.tests_collected.store(0, Ordering::Relaxed);
+        self.duration_micros.store(0, Ordering::Relaxed);
+    }
+
+    fn to_map(&self) -> HashMap<String, usize> {
+        HashMap::from([
+            ("files_parsed".to_string(), self.files_parsed.load(Ordering::Relaxed)),
+            ("cache_hits".to_string(), self.cache_hits.load(Ordering::Relaxed)),
+            ("dirs_listed".to_string(), self.dirs_listed.load(Ordering::Relaxed)),
+            ("result_cache_hits".to_string(), self.result_cache_hits.load(Ordering::Relaxed)),
+            ("files_collected".to_string(), self.files_collected.load(Ordering::Relaxed)),
+            ("tests_collected".to_string(), self.tests_collected.load(Ordering::Relaxed)),
+            ("duration_micros".to_string(), self.duration_micros.load(Ordering::Relaxed)),
+        ])
+    }
 }
 
 /// Test filter for keyword and marker expressions
@@ -59,18 +800,59 @@ struct CacheData {
 struct TestFilter {
     keyword_expr: Option<String>,
     marker_expr: Option<String>,
+    /// Only keep tests without any effective marker
+    unmarked_only: bool,
+    /// Drop tests of classes matching any of these names (exact or glob)
+    exclude_classes: Vec<String>,
+    /// Let `-m` see class and module markers (pytest semantics); otherwise only the test's own
+    inherit_markers: bool,
+    /// Root-relative path globs: files must match an include (if any) and no exclude
+    include_globs: Vec<String>,
+    exclude_globs: Vec<String>,
 }
 
 impl TestFilter {
+    /// Empty or whitespace-only expressions (`-k ""`) mean no filter
     fn new(keyword_expr: Option<String>, marker_expr: Option<String>) -> Self {
         TestFilter {
-            keyword_expr,
-            marker_expr,
+            keyword_expr: keyword_expr.filter(|expr| !expr.trim().is_empty()),
+            marker_expr: marker_expr.filter(|expr| !expr.trim().is_empty()),
+            unmarked_only: false,
+            exclude_classes: Vec::new(),
+            inherit_markers: true,
+            include_globs: Vec::new(),
+            exclude_globs: Vec::new(),
+        }
+    }
+
+    /// Filter selecting only functions and methods that carry no marker at all
+    fn unmarked() -> Self {
+        TestFilter {
+            unmarked_only: true,
+            ..TestFilter::new(None, None)
         }
     }
 
     /// Check if a test item matches the filter criteria
+    /// A parametrized item matches when any of its cases does.
     fn matches(&self, item: &TestItem) -> bool {
+        if item.parametrize_cases.is_empty() {
+            return self.matches_item(item);
+        }
+        item.case_items().iter().any(|case| self.matches_item(case))
+    }
+
+    fn matches_item(&self, item: &TestItem) -> bool {
+        if self.unmarked_only
+            && (matches!(item.item_type, TestItemType::Class) || item.effective_markers().next().is_some())
+        {
+            return false;
+        }
+
+        if !self.exclude_classes.is_empty() && self.in_excluded_class(item) {
+            return false;
+        }
+
         // If no filters, everything matches
         if self.keyword_expr.is_none() && self.marker_expr.is_none() {
             return true;
@@ -93,18 +875,59 @@ impl TestFilter {
         true
     }
 
+    /// Whether a file (root-relative, `/`-separated) is in scope of the include/exclude globs
+    fn matches_path(&self, relative: &str) -> bool {
+        let included =
+            self.include_globs.is_empty() || self.include_globs.iter().any(|glob| matches_path_glob(relative, glob));
+        included && !self.exclude_globs.iter().any(|glob| matches_path_glob(relative, glob))
+    }
+
+    /// Why a rejected item does not match, for the collection trace
+    fn rejection_reason(&self, item: &TestItem) -> String {
+        if !item.parametrize_cases.is_empty() {
+            return "no parametrize case matches".to_string();
+        }
+        if self.unmarked_only
+            && (matches!(item.item_type, TestItemType::Class) || item.effective_markers().next().is_some())
+        {
+            return "not an unmarked test".to_string();
+        }
+        if !self.exclude_classes.is_empty() && self.in_excluded_class(item) {
+            return "in an excluded class".to_string();
+        }
+        if let Some(ref expr) = self.keyword_expr {
+            if !self.matches_keyword(item, expr) {
+                return format!("-k '{}' does not match", expr);
+            }
+        }
+        match self.marker_expr {
+            Some(ref expr) => format!("-m '{}' does not match", expr),
+            None => "filter does not match".to_string(),
+        }
+    }
+
+    /// Whether the item is, or sits inside, an excluded class (at any nesting level)
+    fn in_excluded_class(&self, item: &TestItem) -> bool {
+        let own_name = matches!(item.item_type, TestItemType::Class).then_some(item.name.as_str());
+        item.class_name
+            .iter()
+            .flat_map(|path| path.split("::"))
+            .chain(own_name)
+            .any(|class| self.exclude_classes.iter().any(|pattern| matches_wildcard(class, pattern)))
+    }
+
     /// Check if test matches keyword expression (-k)
     fn matches_keyword(&self, item: &TestItem, expr: &str) -> bool {
         // Build searchable text from test item
-        let mut parts = vec![item.name.to_lowercase()];
+        let mut parts = vec![fold_case(&item.name)];
 
         if let Some(ref class_name) = item.class_name {
-            parts.push(class_name.to_lowercase());
+            parts.push(fold_case(class_name));
         }
 
         // Add filename without extension
         if let Some(filename) = Path::new(&item.file_path).file_stem() {
-            parts.push(filename.to_string_lossy().to_lowercase());
+            parts.push(fold_case(&filename.to_string_lossy()));
         }
 
         let search_text = parts.join(" ");
@@ -114,18 +937,18 @@ impl TestFilter {
 
     /// Check if test matches marker expression (-m)
     fn matches_marker(&self, item: &TestItem, expr: &str) -> bool {
-        let marker_set: HashSet<String> = item
-            .markers
-            .iter()
-            .map(|m| m.to_lowercase())
-            .collect();
+        let marker_set: HashSet<String> = if self.inherit_markers {
+            item.effective_markers().map(|m| fold_case(m)).collect()
+        } else {
+            item.markers.iter().map(|m| fold_case(m)).collect()
+        };
 
         self.evaluate_marker_expression(expr, &marker_set)
     }
 
     /// Evaluate keyword expression against search text
     fn evaluate_expression(&self, expr: &str, search_text: &str) -> bool {
-        let expr = expr.to_lowercase().trim().to_string();
+        let expr = fold_case(expr).trim().to_string();
 
         // Handle simple case: single keyword
         if !expr.contains(" and ") && !expr.contains(" or ") && !expr.starts_with("not ") {
@@ -133,9 +956,8 @@ impl TestFilter {
         }
 
         // Handle NOT
-        if expr.starts_with("not ") {
-            let keyword = expr[4..].trim();
-            return !search_text.contains(keyword);
+        if let Some(keyword) = expr.strip_prefix("not ") {
+            return !search_text.contains(keyword.trim());
         }
 
         // Handle AND (has higher precedence than OR in pytest)
@@ -159,7 +981,7 @@ impl TestFilter {
 
     /// Evaluate marker expression against marker set
     fn evaluate_marker_expression(&self, expr: &str, markers: &HashSet<String>) -> bool {
-        let expr = expr.to_lowercase().trim().to_string();
+        let expr = fold_case(expr).trim().to_string();
 
         // Handle simple case: single marker
         if !expr.contains(" and ") && !expr.contains(" or ") && !expr.starts_with("not ") {
@@ -167,9 +989,8 @@ impl TestFilter {
         }
 
         // Handle NOT
-        if expr.starts_with("not ") {
-            let marker = expr[4..].trim();
-            return !markers.contains(marker);
+        if let Some(marker) = expr.strip_prefix("not ") {
+            return !markers.contains(marker.trim());
         }
 
         // Handle AND
@@ -192,6 +1013,94 @@ impl TestFilter {
     }
 }
 
+/// Read-only test item handed to Python by `collect_items`; fields are attributes named like
+/// the keys of the dict output (`class_name` for `class`)
+/// Not named `TestItem`, which pytest would try to collect as a test class. List attributes
+/// (`markers`, `inherited_markers`, `requested_fixtures`) build a new list on every access,
+/// so read them once when using them repeatedly.
+#[pyclass(frozen)]
+struct CollectedItem {
+    item: TestItem,
+    node_id: String,
+}
+
+#[pymethods]
+impl CollectedItem {
+    #[getter]
+    fn name(&self) -> &str {
+        &self.item.name
+    }
+
+    #[getter]
+    fn qualname(&self) -> &str {
+        &self.item.qualname
+    }
+
+    #[getter]
+    fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    #[getter]
+    fn file_path(&self) -> &str {
+        &self.item.file_path
+    }
+
+    #[getter]
+    fn class_name(&self) -> Option<&str> {
+        self.item.class_name.as_deref()
+    }
+
+    #[getter]
+    fn line(&self) -> usize {
+        self.item.line_number
+    }
+
+    #[getter]
+    fn end_line(&self) -> usize {
+        self.item.end_line
+    }
+
+    #[getter(r#type)]
+    fn item_type(&self) -> String {
+        format!("{:?}", self.item.item_type)
+    }
+
+    #[getter]
+    fn is_async(&self) -> bool {
+        self.item.is_async
+    }
+
+    #[getter]
+    fn markers(&self) -> Vec<String> {
+        self.item.markers.clone()
+    }
+
+    #[getter]
+    fn inherited_markers(&self) -> Vec<String> {
+        self.item.inherited_markers.clone()
+    }
+
+    #[getter]
+    fn requested_fixtures(&self) -> Vec<String> {
+        self.item.requested_fixtures.clone()
+    }
+
+    #[getter]
+    fn parametrize_count(&self) -> Option<usize> {
+        self.item.parametrize_count
+    }
+
+    /// Seconds from a literal `@pytest.mark.timeout(...)` (pytest-timeout), `None` without one
+    fn get_timeout(&self) -> Option<f64> {
+        self.item.timeout
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<CollectedItem {}>", self.node_id)
+    }
+}
+
 /// Fast test collector using Rust
 #[pyclass]
 struct FastCollector {
@@ -202,6 +1111,88 @@ struct FastCollector {
     // Using RwLock for thread-safe interior mutability (works with Rayon parallel iterators)
     cache_path: RwLock<Option<PathBuf>>,
     cache: RwLock<HashMap<String, CacheEntry>>,
+    /// Record files whose tests were all rejected by the filter
+    report_empty_after_filter: bool,
+    empty_after_filter: RwLock<Vec<String>>,
+    /// Minimum number of files handed to each Rayon task (0 = default scheduling)
+    parallel_chunk_size: usize,
+    /// Restrict module-level collection to names listed in `__all__` when present
+    respect_all: bool,
+    parse_error_policy: ParseErrorPolicy,
+    /// Parse errors and warnings recorded during the last collection
+    parse_errors: RwLock<Vec<FileParseError>>,
+    warnings: RwLock<Vec<String>>,
+    stats: CollectionStats,
+    /// Trust cached entries of files not modified since the cache file was written
+    skip_older_than_cache: bool,
+    cache_file_mtime: RwLock<Option<f64>>,
+    /// pytest rootdir that node ids and item paths are reported relative to (may be above `root_path`)
+    rootdir: Option<PathBuf>,
+    /// Count `assert` statements in test bodies (test-smell detection)
+    count_asserts: bool,
+    /// Validate cache entries by content hash in addition to mtime
+    hash_algorithm: Option<HashAlgorithm>,
+    /// Markers whose tests are dropped at parse time, before caching and filtering
+    exclude_markers_hard: Vec<String>,
+    /// Name prefixes identifying test functions and test classes
+    function_prefixes: Vec<String>,
+    class_prefixes: Vec<String>,
+    /// Name prefixes for methods of test classes, overriding `function_prefixes` there when set
+    method_prefixes: Option<Vec<String>>,
+    /// Markers registered via `register_markers` or found by `scan_conftest`
+    registered_markers: HashSet<String>,
+    /// `conftest.py` files found by `scan_conftest`
+    conftests: HashSet<PathBuf>,
+    /// Paths listed in the `collect_ignore` of scanned conftests
+    collect_ignore: HashSet<PathBuf>,
+    /// Names of fixtures defined in scanned conftests
+    conftest_fixtures: HashSet<String>,
+    /// Marker pairs that must not both apply to one test
+    conflicting_markers: Vec<(String, String)>,
+    /// Reuse directory listings whose mtime is unchanged instead of re-listing them
+    incremental_walk: bool,
+    dir_listings: RwLock<HashMap<String, DirListing>>,
+    inherit_markers: bool,
+    /// Record each test's signature (argument names, defaults omitted)
+    capture_signature: bool,
+    /// Collect tests defined inside top-level `if` blocks (except `if __name__ == "__main__":`)
+    collect_conditional_defs: bool,
+    /// Emit one item per parametrized case instead of one item with a count
+    expand_parametrize: bool,
+    /// Only emit runnable items: no class items, parametrized cases expanded
+    leaf_only: bool,
+    /// Seed for a reproducible shuffle of the collected node ids
+    shuffle_seed: Option<u64>,
+    /// Record the version-gated syntax features each file uses
+    detect_syntax_features: bool,
+    /// Give each item a line-independent `order_key`
+    order_keys: bool,
+    /// Record each item's byte offsets
+    capture_offsets: bool,
+    /// How many levels of same-module base classes contribute inherited test methods
+    max_inheritance_depth: usize,
+    /// Reject unrecognized `.fastcollect.toml` keys instead of ignoring them
+    strict_config: bool,
+    /// Count reported for a parametrize whose argvalues are not a literal list or tuple
+    unknown_parametrize_count: Option<usize>,
+    /// Delimiter between the parts of a node id (`::` like pytest)
+    node_id_separator: String,
+    /// Directory prepended to the path of every node id, e.g. `tests/unit` when walking from `unit/`
+    node_id_prefix: String,
+    /// Memoize `collect_node_ids` per `(keyword_expr, marker_expr)`, keyed on the test files' mtimes
+    result_cache_enabled: bool,
+    result_cache: RwLock<HashMap<FilterKey, (String, Vec<String>)>>,
+    /// Items parsed by `build_index`, filtered in memory by `query`
+    index: RwLock<Vec<TestItem>>,
+    /// Record why files and items were or were not collected (see `get_trace`)
+    trace_enabled: bool,
+    trace: RwLock<Vec<String>>,
+    /// Collect from the tree at this git ref instead of the working directory
+    git_ref: Option<String>,
+    /// Maps each node id returned by `collect_node_ids` to the id to report (`set_node_id_rewriter`)
+    node_id_rewriter: Option<NodeIdRewriter>,
+    /// Roots walked instead of `root_path` (like pytest `testpaths`), each with its own ignore patterns
+    roots: Vec<(PathBuf, Vec<String>)>,
 }
 
 #[pymethods]
@@ -226,6 +1217,52 @@ impl FastCollector {
             // PHASE 3: Initialize cache (empty until cache_path is set)
             cache_path: RwLock::new(None),
             cache: RwLock::new(HashMap::new()),
+            report_empty_after_filter: false,
+            empty_after_filter: RwLock::new(Vec::new()),
+            parallel_chunk_size: 0,
+            respect_all: false,
+            parse_error_policy: ParseErrorPolicy::Warn,
+            parse_errors: RwLock::new(Vec::new()),
+            warnings: RwLock::new(Vec::new()),
+            stats: CollectionStats::default(),
+            skip_older_than_cache: false,
+            cache_file_mtime: RwLock::new(None),
+            rootdir: None,
+            count_asserts: false,
+            hash_algorithm: None,
+            exclude_markers_hard: Vec::new(),
+            function_prefixes: vec!["test".to_string()],
+            class_prefixes: vec!["Test".to_string()],
+            method_prefixes: None,
+            registered_markers: HashSet::new(),
+            conftests: HashSet::new(),
+            collect_ignore: HashSet::new(),
+            conftest_fixtures: HashSet::new(),
+            conflicting_markers: Vec::new(),
+            incremental_walk: false,
+            dir_listings: RwLock::new(HashMap::new()),
+            inherit_markers: true,
+            capture_signature: false,
+            collect_conditional_defs: false,
+            expand_parametrize: false,
+            leaf_only: false,
+            shuffle_seed: None,
+            detect_syntax_features: false,
+            order_keys: false,
+            capture_offsets: false,
+            max_inheritance_depth: 10,
+            strict_config: false,
+            unknown_parametrize_count: None,
+            node_id_separator: "::".to_string(),
+            node_id_prefix: String::new(),
+            result_cache_enabled: false,
+            result_cache: RwLock::new(HashMap::new()),
+            index: RwLock::new(Vec::new()),
+            trace_enabled: false,
+            trace: RwLock::new(Vec::new()),
+            git_ref: None,
+            node_id_rewriter: None,
+            roots: Vec::new(),
         }
     }
 
@@ -237,17 +1274,414 @@ impl FastCollector {
         Ok(())
     }
 
-    /// Collect all test files and parse them for test items
-    fn collect(&self, py: Python) -> PyResult<Py<PyAny>> {
-        let test_files = self.find_test_files();
+    /// Rewrite the cache file compactly, pruning entries of deleted files
+    /// Returns the number of bytes saved.
+    fn compact_cache(&self) -> PyResult<usize> {
+        self.compact_cache_file()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Cache compaction failed: {}", e)))
+    }
+
+    /// Enable recording of files that had tests but none matched the filter
+    fn set_report_empty_after_filter(&mut self, enabled: bool) {
+        self.report_empty_after_filter = enabled;
+    }
+
+    /// Files considered by the last filtered collection that yielded no matching tests
+    fn get_empty_after_filter(&self) -> Vec<String> {
+        self.empty_after_filter.read().unwrap().clone()
+    }
+
+    /// Tuning knob: batch at least `chunk_size` files per Rayon task
+    /// Helps suites with many tiny files where per-file task overhead dominates.
+    /// `0` keeps the default per-file scheduling.
+    fn set_parallel_chunk_size(&mut self, chunk_size: usize) {
+        self.parallel_chunk_size = chunk_size;
+    }
+
+    /// Only collect module-level tests exported via `__all__` (modules without it are unaffected)
+    fn set_respect_all(&mut self, enabled: bool) {
+        self.respect_all = enabled;
+        self.invalidate_parsed_items();
+    }
+
+    /// How to handle test files with syntax errors: "skip", "warn" (default) or "error"
+    /// With "error", collection stops at the first offending file and collect methods raise
+    /// `SyntaxError` naming it.
+    fn set_parse_error_policy(&mut self, policy: &str) -> PyResult<()> {
+        self.parse_error_policy = ParseErrorPolicy::from_name(policy).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid parse error policy '{}' (expected 'skip', 'warn' or 'error')",
+                policy
+            ))
+        })?;
+        Ok(())
+    }
+
+    /// Count reported when a parametrize's argvalues are not a literal list or tuple:
+    /// "none" (default, count unknown), "one", or a fixed estimate
+    fn set_unknown_parametrize_behavior(&mut self, behavior: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.unknown_parametrize_count = if let Ok(estimate) = behavior.extract::<usize>() {
+            Some(estimate)
+        } else {
+            parse_unknown_parametrize_behavior(&behavior.extract::<String>()?)
+                .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?
+        };
+        self.invalidate_parsed_items();
+        Ok(())
+    }
+
+    /// Validate cache entries by content hash: "blake3" or "xxhash"
+    /// Files whose mtime changed but whose content did not are still served from the cache.
+    /// `None` or "none" goes back to mtime-only validation.
+    #[pyo3(signature = (algorithm))]
+    fn set_hash_algorithm(&mut self, algorithm: Option<&str>) -> PyResult<()> {
+        self.hash_algorithm = match algorithm {
+            None | Some("none") => None,
+            Some(name) => Some(HashAlgorithm::from_name(name).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Invalid hash algorithm '{}' (expected 'blake3', 'xxhash' or 'none')",
+                    name
+                ))
+            })?),
+        };
+        Ok(())
+    }
+
+    /// Parse errors from the last collection as `(file, message, line, column)`
+    fn get_parse_errors(&self) -> Vec<(String, String, usize, usize)> {
+        self.parse_errors
+            .read()
+            .unwrap()
+            .iter()
+            .map(|e| (e.file_path.clone(), e.message.clone(), e.line, e.column))
+            .collect()
+    }
+
+    /// Warnings recorded during the last collection
+    fn get_warnings(&self) -> Vec<String> {
+        self.warnings.read().unwrap().clone()
+    }
+
+    /// Record a line per collection decision: file discovered, parsed or served from the cache,
+    /// item collected or filtered out (with the reason)
+    fn set_trace(&mut self, enabled: bool) {
+        self.trace_enabled = enabled;
+    }
+
+    /// Trace of the last collection, sorted by file (empty unless `set_trace(true)`)
+    fn get_trace(&self) -> Vec<String> {
+        self.trace.read().unwrap().clone()
+    }
+
+    /// Counters from the last collection (`files_parsed`, `cache_hits`, `dirs_listed`, `result_cache_hits`)
+    fn get_stats(&self) -> HashMap<String, usize> {
+        self.stats.to_map()
+    }
+
+    /// Gauges of the last collection in the Prometheus text exposition format
+    /// (`fastcollect_files`, `fastcollect_tests`, `fastcollect_cache_hits`,
+    /// `fastcollect_parse_errors`, `fastcollect_collection_duration_seconds`)
+    fn metrics_prometheus(&self) -> String {
+        self.prometheus_metrics()
+    }
+
+    /// Serve any cached file whose mtime is not newer than the cache file itself,
+    /// without comparing against the mtime recorded in its entry or touching the file again
+    fn set_skip_older_than_cache(&mut self, enabled: bool) {
+        self.skip_older_than_cache = enabled;
+    }
+
+    /// Report node ids and item `file_path` values relative to pytest's rootdir
+    fn set_rootdir(&mut self, rootdir: String) {
+        self.rootdir = Some(PathBuf::from(rootdir));
+        self.invalidate_parsed_items();
+    }
+
+    /// Record the number of `assert` statements of each test as `assert_count`
+    fn set_count_asserts(&mut self, enabled: bool) {
+        self.count_asserts = enabled;
+        self.invalidate_parsed_items();
+    }
+
+    /// Never collect tests carrying any of these markers (unlike `-m "not x"`, this also affects totals)
+    fn set_exclude_markers_hard(&mut self, markers: Vec<String>) {
+        self.exclude_markers_hard = markers;
+        self.invalidate_parsed_items();
+    }
+
+    /// Apply settings from `.fastcollect.toml` in the root (`test_patterns`, `ignore_patterns`,
+    /// `function_prefixes`, `class_prefixes`, `cache_path`). A missing file is a no-op.
+    fn load_fastcollect_config(&mut self) -> PyResult<()> {
+        self.apply_config_file().map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid .fastcollect.toml: {}", e))
+        })
+    }
+
+    /// Make `load_fastcollect_config` raise on keys it does not recognize (default: ignore them)
+    fn set_strict_config(&mut self, enabled: bool) {
+        self.strict_config = enabled;
+    }
+
+    /// Marker pairs that contradict each other, e.g. `[("skip", "run")]`; tests carrying both
+    /// markers of a pair (own or inherited) are reported in `get_warnings()`
+    fn set_conflicting_markers(&mut self, pairs: Vec<(String, String)>) {
+        self.conflicting_markers = pairs;
+    }
+
+    /// Declare markers as registered (like the `markers` ini option)
+    fn register_markers(&mut self, markers: Vec<String>) {
+        self.registered_markers.extend(markers);
+    }
+
+    /// Register markers declared in `conftest.py` files through
+    /// `config.addinivalue_line("markers", "name: description")`
+    /// Collected files then list the conftests applying to them as `applicable_conftests`, and
+    /// paths in a literal `collect_ignore = [...]` list (relative to the conftest) are not collected.
+    /// Tests named like a conftest fixture are reported in `get_warnings()`.
+    fn scan_conftest(&mut self) {
+        let conftests: Vec<PathBuf> = WalkDir::new(&self.root_path)
+            .into_iter()
+            .filter_entry(|e| !self.should_ignore(e.path()))
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file() && e.file_name() == "conftest.py")
+            .map(|e| e.path().to_path_buf())
+            .collect();
+
+        for conftest in &conftests {
+            let Ok(content) = fs::read_to_string(conftest) else {
+                continue;
+            };
+            if let Ok(module) = ast::Suite::parse(&content, &conftest.to_string_lossy()) {
+                let mut markers = Vec::new();
+                self.extract_marker_registrations(&module, &mut markers);
+                self.registered_markers.extend(markers);
+
+                let dir = conftest.parent().unwrap_or(Path::new(""));
+                let ignored: Vec<PathBuf> = self.extract_collect_ignore(&module).iter().map(|path| dir.join(path)).collect();
+                self.collect_ignore.extend(ignored);
+                let fixtures = self.extract_fixture_names(&module);
+                self.conftest_fixtures.extend(fixtures);
+            }
+        }
+        self.conftests = conftests.into_iter().collect();
+    }
+
+    /// `(node id, marker)` pairs for markers that are neither registered nor builtin,
+    /// catching typos like `@pytest.mark.slwo` the way `--strict-markers` would
+    fn collect_unknown_markers(&self) -> PyResult<Vec<(String, String)>> {
+        let unknown = self.unknown_markers();
+        self.raise_on_parse_error()?;
+        Ok(unknown)
+    }
+
+    /// Node ids of tests carrying none of `required_any_of` (class and module markers count),
+    /// for policies like "every test is one of unit, integration or e2e"
+    fn find_missing_required_markers(&self, required_any_of: Vec<String>) -> PyResult<Vec<String>> {
+        let missing = self.missing_required_markers(&required_any_of);
+        self.raise_on_parse_error()?;
+        Ok(missing)
+    }
+
+    /// Registered markers (`register_markers`, `scan_conftest`) that no collected test carries,
+    /// sorted; candidates for removal from the registrations
+    fn find_unused_markers(&self) -> PyResult<Vec<String>> {
+        let unused = self.unused_markers();
+        self.raise_on_parse_error()?;
+        Ok(unused)
+    }
+
+    /// Node ids of tests in files importing `module` or one of its submodules, for impact analysis
+    fn tests_importing(&self, module: String) -> PyResult<Vec<String>> {
+        let node_ids = self.node_ids_importing(&module);
+        self.raise_on_parse_error()?;
+        Ok(node_ids)
+    }
+
+    /// Python files not matching the test patterns that define top-level test functions,
+    /// relative to the root and sorted; likely misnamed test files (`tests_login.py`)
+    fn find_suspicious_files(&self) -> Vec<String> {
+        self.suspicious_files()
+    }
+
+    /// Distinct directories (relative to the root, sorted) holding discovered test files,
+    /// e.g. for coverage or watch paths; the root itself is `"."`
+    fn test_directories(&self) -> Vec<String> {
+        self.test_file_directories()
+    }
+
+    /// Skip re-listing directories whose mtime is unchanged since the last walk
+    /// Listings are kept in the cache file so later sessions benefit too.
+    fn set_incremental_walk(&mut self, enabled: bool) {
+        self.incremental_walk = enabled;
+    }
+
+    /// Walk `path` (relative to the root, like a pytest `testpaths` entry) instead of the whole root
+    /// `ignores` apply within that subtree only, on top of the global ignore patterns.
+    fn add_root_with_ignores(&mut self, path: String, ignores: Vec<String>) {
+        self.roots.push((self.root_path.join(path), ignores));
+    }
+
+    /// Collect tests defined inside top-level `if` blocks (e.g. `if sys.platform == "linux":`)
+    /// Blocks guarded by `if __name__ == "__main__":` only run as a script and are never collected.
+    fn set_collect_conditional_defs(&mut self, enabled: bool) {
+        self.collect_conditional_defs = enabled;
+        self.invalidate_parsed_items();
+    }
+
+    /// Delimiter used between file, classes and test in node ids (default `::`), e.g. `>>`
+    /// for `tests/test_api.py>>TestAPI>>test_get`
+    fn set_node_id_separator(&mut self, separator: String) -> PyResult<()> {
+        if separator.is_empty() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "Node id separator must not be empty",
+            ));
+        }
+        self.node_id_separator = separator;
+        Ok(())
+    }
+
+    /// Prepend a directory to the path of every node id, so ids walked from `unit/` read
+    /// `tests/unit/test_x.py::test_y` with the prefix `tests/unit`
+    fn set_node_id_prefix(&mut self, prefix: String) {
+        self.node_id_prefix = prefix.trim_matches('/').to_string();
+    }
+
+    /// Pass every node id returned by `collect_node_ids` through `rewriter(node_id) -> str`,
+    /// e.g. to map the filesystem layout onto logical ids; `None` removes the rewriter.
+    /// The callable runs on the calling thread once collection has finished. Only
+    /// `collect_node_ids` applies it: other outputs (JSON, records, reports, `TestItem`
+    /// objects) keep the generated ids.
+    #[pyo3(signature = (rewriter=None))]
+    fn set_node_id_rewriter(&mut self, rewriter: Option<Py<PyAny>>) {
+        self.node_id_rewriter = rewriter.map(|rewriter| -> NodeIdRewriter {
+            Box::new(move |node_id| Python::attach(|py| rewriter.call1(py, (node_id,))?.extract(py)))
+        });
+    }
+
+    /// Remember `collect_node_ids` results per filter; a repeated filter is answered without
+    /// parsing or filtering as long as no test file was added, removed or modified
+    fn set_result_cache(&mut self, enabled: bool) {
+        self.result_cache_enabled = enabled;
+        self.result_cache.write().unwrap().clear();
+    }
+
+    /// Report the version-gated syntax each file uses as `syntax_features`
+    /// (`async`, `fstring`, `walrus`, `match`, `except_star`, `type_params`)
+    fn set_detect_syntax_features(&mut self, enabled: bool) {
+        self.detect_syntax_features = enabled;
+        self.invalidate_parsed_items();
+    }
+
+    /// Collect methods of test classes by these name prefixes instead of the function prefixes
+    /// (e.g. `["it_", "should_"]` for BDD-style suites); free functions are unaffected
+    fn set_method_prefixes(&mut self, prefixes: Vec<String>) {
+        self.method_prefixes = Some(prefixes);
+        self.invalidate_parsed_items();
+    }
+
+    /// Bound on the levels of base classes searched for inherited test methods (default 10)
+    /// Deeper bases are ignored with a warning.
+    fn set_max_inheritance_depth(&mut self, depth: usize) {
+        self.max_inheritance_depth = depth;
+        self.invalidate_parsed_items();
+    }
+
+    /// Give each item an `order_key` built from its file and qualname (not its line), so
+    /// persisted test orders survive code being added above a test
+    fn set_order_keys(&mut self, enabled: bool) {
+        self.order_keys = enabled;
+        self.invalidate_parsed_items();
+    }
+
+    /// Record each item's byte offsets in its file as `byte_start` and `byte_end`
+    fn set_capture_offsets(&mut self, enabled: bool) {
+        self.capture_offsets = enabled;
+        self.invalidate_parsed_items();
+    }
+
+    /// Emit one item per parametrized case (`test_x[1]`, with `parametrize_id`) instead of a single
+    /// item carrying `parametrize_count`
+    /// Tests whose case ids are not statically known (stacked or class-level parametrize) stay
+    /// a single item.
+    fn set_expand_parametrize(&mut self, enabled: bool) {
+        self.expand_parametrize = enabled;
+        self.invalidate_parsed_items();
+    }
+
+    /// Emit only runnable leaves: class items are dropped and parametrized cases expanded as with
+    /// `set_expand_parametrize(true)` (tests with unknown case ids stay one item, whose node id
+    /// selects all of its cases)
+    fn set_leaf_only(&mut self, enabled: bool) {
+        self.leaf_only = enabled;
+        self.invalidate_parsed_items();
+    }
+
+    /// Hand out node ids in a deterministic permutation of their usual order, seeded by `seed`
+    /// (same seed, same order); `None` restores file and line order
+    #[pyo3(signature = (seed=None))]
+    fn set_shuffle_seed(&mut self, seed: Option<u64>) {
+        self.shuffle_seed = seed;
+    }
+
+    /// Collect the test files of the tree at a git ref (`"HEAD"`, a branch, tag or commit) by
+    /// reading their blobs, without checking it out; node ids use the tree paths.
+    /// Applies to the filtered collection methods. `None` returns to the working directory.
+    /// Needs the `git` Cargo feature; without it collection warns and returns nothing.
+    #[pyo3(signature = (reference=None))]
+    fn set_git_ref(&mut self, reference: Option<String>) {
+        self.git_ref = reference;
+    }
+
+    /// Record each test's signature, e.g. `test_foo(self, db, client)`, as `signature`
+    fn set_capture_signature(&mut self, enabled: bool) {
+        self.capture_signature = enabled;
+        self.invalidate_parsed_items();
+    }
+
+    /// Whether `-m` filtering considers class and module markers (default, like pytest)
+    /// When disabled only the test's own decorators count; inherited markers are still reported.
+    fn set_inherit_markers(&mut self, enabled: bool) {
+        self.inherit_markers = enabled;
+    }
+
+    /// Clear the in-memory cache, index, warnings, parse errors and stats, keeping the configuration
+    fn reset(&self) {
+        self.invalidate_parsed_items();
+        self.dir_listings.write().unwrap().clear();
+        self.result_cache.write().unwrap().clear();
+        self.index.write().unwrap().clear();
+        *self.cache_file_mtime.write().unwrap() = None;
+        self.begin_collection();
+    }
+
+    /// Parse every test file once (through the cache) into an in-memory index for `query`;
+    /// returns the number of indexed items
+    fn build_index(&self) -> PyResult<usize> {
+        let count = self.rebuild_index();
+        self.raise_on_parse_error()?;
+        Ok(count)
+    }
+
+    /// JSON list of the indexed items passing the filters, without touching the disk
+    /// The index reflects the tree as of the last `build_index()`.
+    #[pyo3(signature = (keyword_expr=None, marker_expr=None))]
+    fn query(&self, keyword_expr: Option<String>, marker_expr: Option<String>) -> PyResult<String> {
+        let items = self.query_index(&TestFilter::new(keyword_expr, marker_expr));
+        to_json(&items)
+    }
+
+    /// Collect all test files and parse them for test items
+    fn collect(&self, py: Python) -> PyResult<Py<PyAny>> {
+        self.begin_collection();
+        let test_files = self.find_test_files();
 
         // Use rayon for parallel processing
         let all_items: Vec<TestItem> = test_files
             .par_iter()
-            .flat_map(|file_path| {
-                self.parse_test_file(file_path).unwrap_or_default()
-            })
+            .with_min_len(self.min_task_len())
+            .flat_map(|file_path| self.parse_file_recorded(file_path).items)
             .collect();
+        self.raise_on_parse_error()?;
 
         // Convert to Python dict
         self.items_to_python(py, &all_items)
@@ -255,955 +1689,6424 @@ impl FastCollector {
 
     /// Collect with file metadata (includes modification times)
     fn collect_with_metadata(&self, py: Python) -> PyResult<Py<PyAny>> {
+        self.begin_collection();
         let test_files = self.find_test_files();
 
         // Use rayon for parallel processing
         let file_metadata: Vec<FileMetadata> = test_files
             .par_iter()
+            .with_min_len(self.min_task_len())
             .filter_map(|file_path| {
                 // Get file modification time
-                let mtime = match fs::metadata(file_path) {
-                    Ok(metadata) => {
-                        match metadata.modified() {
-                            Ok(time) => {
-                                match time.duration_since(SystemTime::UNIX_EPOCH) {
-                                    Ok(duration) => duration.as_secs_f64(),
-                                    Err(_) => 0.0,
-                                }
-                            }
-                            Err(_) => 0.0,
-                        }
-                    }
-                    Err(_) => 0.0,
-                };
+                let mtime = file_mtime(file_path);
 
                 // Parse test items
-                let test_items = self.parse_test_file(file_path).unwrap_or_default();
+                let parsed = self.parse_file_recorded(file_path);
 
-                if test_items.is_empty() {
+                if parsed.items.is_empty() {
                     return None;
                 }
 
-                Some(FileMetadata {
-                    path: file_path.to_string_lossy().to_string(),
-                    mtime,
-                    test_items,
-                })
+                Some(FileMetadata::new(file_path.to_string_lossy().to_string(), mtime, parsed))
             })
             .collect();
+        self.raise_on_parse_error()?;
 
         // Convert to Python dict
         self.metadata_to_python(py, &file_metadata)
     }
 
+    /// Collect metadata (as `collect_with_metadata`) together with an inverted marker index
+    /// `{marker: [node ids]}` over functions and methods, from a single collection
+    fn collect_with_index(&self, py: Python) -> PyResult<(Py<PyAny>, MarkerIndex)> {
+        let file_metadata = self.collect_filtered(&TestFilter::new(None, None));
+        self.raise_on_parse_error()?;
+        let index = self.marker_index(&file_metadata);
+        Ok((self.metadata_to_python(py, &file_metadata)?, index))
+    }
+
+    /// Heuristic map from module-under-test names to node ids, inferred from test file names
+    /// (`test_auth.py` and `auth_test.py` both map to `auth`), as a coverage-mapping scaffold
+    fn infer_coverage_targets(&self) -> PyResult<BTreeMap<String, Vec<String>>> {
+        let targets = self.coverage_targets();
+        self.raise_on_parse_error()?;
+        Ok(targets)
+    }
+
+    /// Collect only the tree's containers: one entry per test file listing its test classes,
+    /// without methods or functions (expand a file later with `collect_file`)
+    fn collect_containers(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let file_metadata = self.collect_container_metadata();
+        self.raise_on_parse_error()?;
+        self.metadata_to_python(py, &file_metadata)
+    }
+
+    /// Metadata of a single node id (`path::Class::test[id]`), parsing only its file
+    /// Returns `None` when the file or the item does not exist.
+    fn collect_one<'py>(&self, py: Python<'py>, node_id: String) -> PyResult<Option<Bound<'py, PyDict>>> {
+        self.begin_collection();
+        let item = self.find_node(&node_id);
+        self.raise_on_parse_error()?;
+        item.map(|item| self.item_to_python(py, &item)).transpose()
+    }
+
     /// Collect tests from a specific file
     fn collect_file(&self, py: Python, file_path: String) -> PyResult<Py<PyAny>> {
         let path = PathBuf::from(file_path);
-        let items = self.parse_test_file(&path).unwrap_or_default();
+        self.begin_collection();
+        let items = self.parse_file_recorded(&path).items;
+        self.raise_on_parse_error()?;
         self.items_to_python(py, &items)
     }
 
+    /// Collect the test files of one directory (relative to the root) as JSON, like `collect_json`
+    /// With `recursive=False` only its direct children are listed, not the subtree.
+    #[pyo3(signature = (dir, recursive=true))]
+    fn collect_dir(&self, dir: String, recursive: bool) -> PyResult<String> {
+        self.begin_collection();
+        let file_metadata = self.collect_directory(&self.root_path.join(dir), recursive);
+        self.raise_on_parse_error()?;
+
+        to_json(&file_metadata)
+    }
+
     /// Collect all test files and return metadata as JSON string
     /// This is MUCH faster than building PyDict/PyList objects across FFI boundary
     fn collect_json(&self) -> PyResult<String> {
+        self.begin_collection();
         let test_files = self.find_test_files();
 
         // Use rayon for parallel processing
         let file_metadata: Vec<FileMetadata> = test_files
             .par_iter()
+            .with_min_len(self.min_task_len())
             .filter_map(|file_path| {
                 // Get file modification time
-                let mtime = match fs::metadata(file_path) {
-                    Ok(metadata) => {
-                        match metadata.modified() {
-                            Ok(time) => {
-                                match time.duration_since(SystemTime::UNIX_EPOCH) {
-                                    Ok(duration) => duration.as_secs_f64(),
-                                    Err(_) => 0.0,
-                                }
-                            }
-                            Err(_) => 0.0,
-                        }
-                    }
-                    Err(_) => 0.0,
-                };
+                let mtime = file_mtime(file_path);
 
                 // Parse test items
-                let test_items = self.parse_test_file(file_path).unwrap_or_default();
+                let parsed = self.parse_file_recorded(file_path);
 
-                if test_items.is_empty() {
+                if parsed.items.is_empty() {
                     return None;
                 }
 
-                Some(FileMetadata {
-                    path: file_path.to_string_lossy().to_string(),
-                    mtime,
-                    test_items,
-                })
+                Some(FileMetadata::new(file_path.to_string_lossy().to_string(), mtime, parsed))
             })
             .collect();
+        self.raise_on_parse_error()?;
 
         // Serialize to JSON in one go - much faster than thousands of FFI calls!
-        serde_json::to_string(&file_metadata)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("JSON serialization failed: {}", e)))
+        to_json(&file_metadata)
     }
 
     /// Collect with filtering applied in Rust (MUCH faster than Python filtering)
     /// This is the "quick win" optimization - filters tests during Rayon parallel iteration
-    #[pyo3(signature = (keyword_expr=None, marker_expr=None))]
+    #[pyo3(signature = (keyword_expr=None, marker_expr=None, exclude_classes=None))]
     fn collect_json_filtered(
         &self,
         keyword_expr: Option<String>,
         marker_expr: Option<String>,
+        exclude_classes: Option<Vec<String>>,
     ) -> PyResult<String> {
-        let test_files = self.find_test_files();
-        let filter = TestFilter::new(keyword_expr, marker_expr);
-
-        // PHASE 3: Use cache to avoid re-parsing unchanged files
-        // Use rayon for parallel processing WITH caching AND filtering
-        let file_metadata: Vec<FileMetadata> = test_files
-            .par_iter()
-            .filter_map(|file_path| {
-                let file_path_str = file_path.to_string_lossy().to_string();
+        let filter = TestFilter {
+            exclude_classes: exclude_classes.unwrap_or_default(),
+            ..TestFilter::new(keyword_expr, marker_expr)
+        };
+        let file_metadata = self.collect_filtered(&filter);
+        self.raise_on_parse_error()?;
 
-                // Get file modification time
-                let mtime = match fs::metadata(file_path) {
-                    Ok(metadata) => {
-                        match metadata.modified() {
-                            Ok(time) => {
-                                match time.duration_since(SystemTime::UNIX_EPOCH) {
-                                    Ok(duration) => duration.as_secs_f64(),
-                                    Err(_) => 0.0,
-                                }
-                            }
-                            Err(_) => 0.0,
-                        }
-                    }
-                    Err(_) => 0.0,
-                };
+        // Serialize to JSON
+        to_json(&file_metadata)
+    }
 
-                // PHASE 3: Try to get items from cache first
-                let all_items = if let Some(cached_items) = self.get_cached_items(&file_path_str, mtime) {
-                    // Cache hit! Use cached items (avoids AST parsing)
-                    cached_items
-                } else {
-                    // Cache miss - parse file and update cache
-                    let parsed_items = self.parse_test_file(file_path).unwrap_or_default();
-                    self.update_cache(file_path_str.clone(), mtime, parsed_items.clone());
-                    parsed_items
-                };
+    /// Like `collect_json_filtered`, restricted to files (relative to the root) matching any
+    /// `include_globs` (all files when empty) and none of `exclude_globs`, e.g. `tests/**`
+    /// `*` matches within one path segment; `**` spans directories.
+    #[pyo3(signature = (include_globs, exclude_globs, keyword_expr=None, marker_expr=None))]
+    fn collect_json_scoped(
+        &self,
+        include_globs: Vec<String>,
+        exclude_globs: Vec<String>,
+        keyword_expr: Option<String>,
+        marker_expr: Option<String>,
+    ) -> PyResult<String> {
+        let filter = TestFilter {
+            include_globs,
+            exclude_globs,
+            ..TestFilter::new(keyword_expr, marker_expr)
+        };
+        let file_metadata = self.collect_filtered(&filter);
+        self.raise_on_parse_error()?;
 
-                // CRITICAL: Apply filter HERE in Rust, not in Python!
-                // This avoids creating Python objects for filtered-out tests
-                let test_items: Vec<TestItem> = all_items
-                    .into_iter()
-                    .filter(|item| filter.matches(item))
-                    .collect();
+        to_json(&file_metadata)
+    }
 
-                // Skip file if no matching tests
-                if test_items.is_empty() {
-                    return None;
-                }
+    /// Collect only the listed tests: `allow` maps file paths (relative to the rootdir or root)
+    /// to the test names to keep there. Other files are not parsed at all.
+    fn collect_json_allowlist(&self, allow: HashMap<String, Vec<String>>) -> PyResult<String> {
+        self.begin_collection();
+        let file_metadata = self.collect_allowlisted(&allow);
+        self.raise_on_parse_error()?;
 
-                Some(FileMetadata {
-                    path: file_path_str,
-                    mtime,
-                    test_items,
-                })
-            })
-            .collect();
+        to_json(&file_metadata)
+    }
 
-        // PHASE 3: Save cache after collection (non-fatal if it fails)
-        let _ = self.save_cache();
+    /// Collect the items whose node id starts with any of `prefixes` at a node boundary,
+    /// e.g. `tests/test_auth.py::TestAuth` for everything in that class (but not `TestAuthz`)
+    fn collect_json_by_prefix(&self, prefixes: Vec<String>) -> PyResult<String> {
+        let file_metadata = self.collect_by_prefix(&prefixes);
+        self.raise_on_parse_error()?;
 
-        // Serialize to JSON
-        serde_json::to_string(&file_metadata)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("JSON serialization failed: {}", e)))
+        to_json(&file_metadata)
     }
-}
 
-impl FastCollector {
-    /// PHASE 3: Load cache from disk
-    fn load_cache(&self) {
-        let cache_path_opt = self.cache_path.read().unwrap().clone();
-        if let Some(cache_path) = cache_path_opt {
-            if cache_path.exists() {
-                match fs::read_to_string(&cache_path) {
-                    Ok(contents) => {
-                        match serde_json::from_str::<CacheData>(&contents) {
-                            Ok(cache_data) => {
-                                // Check version
-                                if cache_data.version == CACHE_VERSION {
-                                    *self.cache.write().unwrap() = cache_data.entries;
-                                } else {
-                                    // Version mismatch, start fresh
-                                    self.cache.write().unwrap().clear();
-                                }
-                            }
-                            Err(_) => {
-                                // Corrupted cache, start fresh
-                                self.cache.write().unwrap().clear();
-                            }
-                        }
-                    }
-                    Err(_) => {
-                        // Can't read cache, start fresh
-                        self.cache.write().unwrap().clear();
-                    }
-                }
-            }
-        }
+    /// Collect with filtering into a `tests` table of the SQLite database at `db_path`
+    /// (columns `file`, `class`, `name`, `line`, `type`, `markers` as a JSON array, `node_id`).
+    /// The table is replaced on every call; returns the number of rows written.
+    #[pyo3(signature = (db_path, keyword_expr=None, marker_expr=None))]
+    fn collect_to_sqlite(&self, db_path: String, keyword_expr: Option<String>, marker_expr: Option<String>) -> PyResult<usize> {
+        let filter = TestFilter::new(keyword_expr, marker_expr);
+        let file_metadata = self.collect_filtered(&filter);
+        self.raise_on_parse_error()?;
+
+        self.write_sqlite(Path::new(&db_path), &file_metadata)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to write {}: {}", db_path, e)))
     }
 
-    /// PHASE 3: Save cache to disk
-    fn save_cache(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let cache_path_opt = self.cache_path.read().unwrap().clone();
-        if let Some(cache_path) = cache_path_opt {
-            // Ensure parent directory exists
-            if let Some(parent) = cache_path.parent() {
-                fs::create_dir_all(parent)?;
-            }
+    /// Re-collect files reported as changed (paths relative to the root) for watch mode, as JSON
+    /// A listed file is served from the cache when its mtime did not move or, for saves within
+    /// `min_interval` seconds of its cached entry, when its content hash (`set_hash_algorithm`)
+    /// still matches, so touches without edits cost no parsing.
+    #[pyo3(signature = (changed, min_interval=0.0))]
+    fn collect_changed(&self, changed: Vec<String>, min_interval: f64) -> PyResult<String> {
+        self.begin_collection();
+        let paths: Vec<PathBuf> = changed.iter().map(|path| self.root_path.join(path)).collect();
+        let file_metadata = self.collect_changed_files(&paths, min_interval);
+        self.raise_on_parse_error()?;
+
+        to_json(&file_metadata)
+    }
 
-            let cache_data = CacheData {
-                version: CACHE_VERSION.to_string(),
-                entries: self.cache.read().unwrap().clone(),
-            };
+    /// Collect the given `(path, mtime)` files (paths relative to the root), validating the cache
+    /// against the supplied mtimes instead of stat'ing each file; only cache misses are read
+    #[pyo3(signature = (files, keyword_expr=None, marker_expr=None))]
+    fn collect_json_with_mtimes(
+        &self,
+        files: Vec<(String, f64)>,
+        keyword_expr: Option<String>,
+        marker_expr: Option<String>,
+    ) -> PyResult<String> {
+        self.begin_collection();
+        let filter = self.configured_filter(&TestFilter::new(keyword_expr, marker_expr));
+        let files: Vec<(PathBuf, f64)> = files.into_iter().map(|(path, mtime)| (self.root_path.join(path), mtime)).collect();
+        let file_metadata = self.collect_with_mtimes(&files, &filter);
+        self.raise_on_parse_error()?;
 
-            let json = serde_json::to_string_pretty(&cache_data)?;
-            fs::write(cache_path, json)?;
-        }
-        Ok(())
+        to_json(&file_metadata)
     }
 
-    /// PHASE 3: Get cached data for a file if it's still valid
-    fn get_cached_items(&self, file_path: &str, current_mtime: f64) -> Option<Vec<TestItem>> {
-        let cache = self.cache.read().unwrap();
-        if let Some(entry) = cache.get(file_path) {
-            // Check if mtime matches (within tolerance)
-            if (entry.mtime - current_mtime).abs() < MTIME_TOLERANCE_SECONDS {
-                return Some(entry.items.clone());
-            }
-        }
-        None
+    /// Collect with filtering and return MessagePack bytes (same structure as `collect_json_filtered`)
+    /// Smaller and faster to decode than JSON; decode with `msgpack.unpackb()` on the Python side
+    #[pyo3(signature = (keyword_expr=None, marker_expr=None))]
+    fn collect_msgpack<'py>(
+        &self,
+        py: Python<'py>,
+        keyword_expr: Option<String>,
+        marker_expr: Option<String>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let filter = TestFilter::new(keyword_expr, marker_expr);
+        let file_metadata = self.collect_filtered(&filter);
+        self.raise_on_parse_error()?;
+
+        let bytes = encode_msgpack(&file_metadata)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("MessagePack serialization failed: {}", e)))?;
+        Ok(PyBytes::new(py, &bytes))
     }
 
-    /// PHASE 3: Update cache with newly parsed data
-    fn update_cache(&self, file_path: String, mtime: f64, items: Vec<TestItem>) {
-        self.cache.write().unwrap().insert(file_path, CacheEntry { mtime, items });
+    /// Stream filtered collection results: `callback` receives each file's metadata dict
+    /// (`path`, `mtime`, `items`) as soon as the file is parsed, instead of one blob at the end.
+    /// `on_error`, when given, receives `(file, message, line, column)` for each unparseable file
+    /// as it is found (unless the parse error policy is `"skip"`).
+    /// Parsing runs on Rayon threads; the callbacks are always invoked on the calling thread.
+    #[pyo3(signature = (callback, keyword_expr=None, marker_expr=None, on_error=None))]
+    fn collect_stream(
+        &self,
+        py: Python,
+        callback: Py<PyAny>,
+        keyword_expr: Option<String>,
+        marker_expr: Option<String>,
+        on_error: Option<Py<PyAny>>,
+    ) -> PyResult<()> {
+        let filter = TestFilter::new(keyword_expr, marker_expr);
+        let result = RefCell::new(Ok(()));
+
+        self.collect_streaming(
+            &filter,
+            |file_meta| {
+                // After the first callback error keep draining results but stop calling into Python
+                if result.borrow().is_err() {
+                    return;
+                }
+                *result.borrow_mut() = self
+                    .file_metadata_to_python(py, &file_meta)
+                    .and_then(|file_dict| {
+                        file_dict.set_item("path", &file_meta.path)?;
+                        callback.call1(py, (file_dict,))
+                    })
+                    .map(|_| ());
+            },
+            |error| {
+                let Some(ref on_error) = on_error else {
+                    return;
+                };
+                if result.borrow().is_err() {
+                    return;
+                }
+                *result.borrow_mut() = on_error
+                    .call1(py, (error.file_path, error.message, error.line, error.column))
+                    .map(|_| ());
+            },
+        );
+
+        result.into_inner()?;
+        self.raise_on_parse_error()
     }
 
-    /// Find all test files in the directory tree
-    fn find_test_files(&self) -> Vec<PathBuf> {
-        WalkDir::new(&self.root_path)
-            .into_iter()
-            .filter_entry(|e| {
-                // Skip ignored directories
-                !self.should_ignore(e.path())
-            })
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-            .filter(|e| self.is_test_file(e.path()))
-            .map(|e| e.path().to_path_buf())
-            .collect()
+    /// Collect the items of a file whose span overlaps the 1-indexed `start_line..=end_line` range
+    fn collect_file_range(&self, file_path: String, start_line: usize, end_line: usize) -> PyResult<String> {
+        self.begin_collection();
+        let file_metadata = self.collect_lines(Path::new(&file_path), start_line, end_line);
+        self.raise_on_parse_error()?;
+
+        to_json(&file_metadata)
     }
 
-    /// Check if a path should be ignored
-    fn should_ignore(&self, path: &Path) -> bool {
-        if let Some(name) = path.file_name() {
-            let name_str = name.to_string_lossy();
-            for pattern in &self.ignore_patterns {
-                if pattern.contains('*') {
-                    // Simple wildcard matching
-                    if self.matches_wildcard(&name_str, pattern) {
-                        return true;
-                    }
-                } else if name_str == pattern.as_str() {
-                    return true;
-                }
-            }
-        }
-        false
+    /// Collect from a readable object (`io.StringIO`, packaged resource, ...) instead of the filesystem
+    /// `reader.read()` may return `str` or UTF-8 `bytes`; `virtual_path` is used as the file path.
+    fn collect_reader(&self, py: Python, reader: Py<PyAny>, virtual_path: String) -> PyResult<String> {
+        let source = reader.call_method0(py, "read")?;
+        let content = match source.extract::<String>(py) {
+            Ok(text) => text,
+            Err(_) => String::from_utf8(source.extract::<Vec<u8>>(py)?).map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{} is not valid UTF-8: {}", virtual_path, e))
+            })?,
+        };
+
+        self.begin_collection();
+        let file_metadata = self.collect_source(&content, &virtual_path);
+        self.raise_on_parse_error()?;
+
+        to_json(&file_metadata)
     }
 
-    /// Simple wildcard matching (supports * anywhere in pattern)
-    fn matches_wildcard(&self, text: &str, pattern: &str) -> bool {
-        // Split pattern by '*'
-        let parts: Vec<&str> = pattern.split('*').collect();
+    /// Collect a single module given its dotted path (e.g. `pkg.sub.test_x`) relative to the root
+    fn collect_module(&self, dotted: String) -> PyResult<String> {
+        let path = self.resolve_module(&dotted).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyModuleNotFoundError, _>(format!(
+                "Cannot resolve module '{}' under {}",
+                dotted,
+                self.root_path.display()
+            ))
+        })?;
+
+        self.begin_collection();
+        let file_metadata = self.collect_module_file(&path);
+        self.raise_on_parse_error()?;
+
+        to_json(&file_metadata)
+    }
 
-        if parts.len() == 1 {
-            // No wildcards, exact match
-            return text == pattern;
-        }
+    /// Collect as a columnar dict of parallel lists, one entry per item
+    /// (`file_path`, `name`, `class_name`, `line`, `type`, `markers`): feeds `pandas.DataFrame(...)` directly
+    fn collect_columns(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let file_metadata = self.collect_filtered(&TestFilter::new(None, None));
+        self.raise_on_parse_error()?;
 
-        let mut current_pos = 0;
+        let columns = ItemColumns::from_metadata(&file_metadata);
+        let result = PyDict::new(py);
+        result.set_item("file_path", columns.file_path)?;
+        result.set_item("name", columns.name)?;
+        result.set_item("class_name", columns.class_name)?;
+        result.set_item("line", columns.line)?;
+        result.set_item("type", columns.item_type)?;
+        result.set_item("markers", columns.markers)?;
+        Ok(result.into())
+    }
 
-        for (i, part) in parts.iter().enumerate() {
-            if part.is_empty() {
-                continue;
-            }
+    /// Collect only tests without any marker (including class and module markers)
+    /// Useful in CI to enforce that every new test is marked
+    fn collect_json_unmarked(&self) -> PyResult<String> {
+        let file_metadata = self.collect_filtered(&TestFilter::unmarked());
+        self.raise_on_parse_error()?;
 
-            if i == 0 {
-                // First part must match at start
-                if !text.starts_with(part) {
-                    return false;
-                }
-                current_pos = part.len();
-            } else if i == parts.len() - 1 {
-                // Last part must match at end
-                if !text.ends_with(part) {
-                    return false;
-                }
-                // Check that we haven't gone past the end
-                if current_pos > text.len() - part.len() {
-                    return false;
-                }
-            } else {
-                // Middle parts can match anywhere after current position
-                if let Some(pos) = text[current_pos..].find(part) {
-                    current_pos += pos + part.len();
-                } else {
-                    return false;
-                }
-            }
+        to_json(&file_metadata)
+    }
+
+    /// Collect pytest node ids (functions and methods) matching the filters
+    #[pyo3(signature = (keyword_expr=None, marker_expr=None))]
+    fn collect_node_ids(&self, keyword_expr: Option<String>, marker_expr: Option<String>) -> PyResult<Vec<String>> {
+        let filter = TestFilter::new(keyword_expr, marker_expr);
+        // Trees at a git ref have no mtimes to validate results against
+        let node_ids = if self.result_cache_enabled && self.git_ref.is_none() {
+            self.cached_node_ids(&filter)
+        } else {
+            self.filtered_node_ids(&filter)
+        };
+        self.raise_on_parse_error()?;
+        match self.node_id_rewriter {
+            Some(ref rewriter) => rewrite_node_ids(node_ids, rewriter),
+            None => Ok(node_ids),
         }
+    }
 
-        true
+    /// Single digest over the sorted filtered node ids, for cheap "did the suite change" checks
+    /// With `include_bodies`, each test's source lines are hashed too, so edits to a test
+    /// change the digest. Uses the configured hash algorithm (blake3 by default).
+    #[pyo3(signature = (keyword_expr=None, marker_expr=None, include_bodies=false))]
+    fn collect_digest(&self, keyword_expr: Option<String>, marker_expr: Option<String>, include_bodies: bool) -> PyResult<String> {
+        let filter = TestFilter::new(keyword_expr, marker_expr);
+        let digest = self.suite_digest(&filter, include_bodies);
+        self.raise_on_parse_error()?;
+        Ok(digest)
     }
 
-    /// Check if a file is a test file based on naming patterns
-    fn is_test_file(&self, path: &Path) -> bool {
-        if let Some(name) = path.file_name() {
-            let name_str = name.to_string_lossy();
-            if !name_str.ends_with(".py") {
-                return false;
-            }
-            for pattern in &self.test_patterns {
-                if self.matches_wildcard(&name_str, pattern) {
-                    return true;
-                }
-            }
-        }
-        false
+    /// Numbers of `(functions, classes, methods)` passing the filters, for summary lines
+    #[pyo3(signature = (keyword_expr=None, marker_expr=None))]
+    fn collect_type_counts(&self, keyword_expr: Option<String>, marker_expr: Option<String>) -> PyResult<(usize, usize, usize)> {
+        let filter = TestFilter::new(keyword_expr, marker_expr);
+        let counts = self.type_counts(&filter);
+        self.raise_on_parse_error()?;
+        Ok(counts)
     }
 
-    /// Parse a test file and extract test items
-    fn parse_test_file(&self, path: &Path) -> Result<Vec<TestItem>, Box<dyn std::error::Error>> {
-        let content = fs::read_to_string(path)?;
-        let file_path = path.to_string_lossy().to_string();
+    /// Collect filtered items as `CollectedItem` objects with read-only attributes (`item.name`,
+    /// `item.markers`, ...), skipping the construction of a dict per item
+    #[pyo3(signature = (keyword_expr=None, marker_expr=None))]
+    fn collect_items(&self, py: Python, keyword_expr: Option<String>, marker_expr: Option<String>) -> PyResult<Vec<Py<CollectedItem>>> {
+        let items = self.item_objects(&TestFilter::new(keyword_expr, marker_expr));
+        self.raise_on_parse_error()?;
+        items.into_iter().map(|item| Py::new(py, item)).collect()
+    }
 
-        let module = match ast::Suite::parse(&content, &file_path) {
-            Ok(m) => m,
-            Err(_) => return Ok(Vec::new()), // Skip files with parse errors
-        };
+    /// Collect all items as flat tuples `(file, name, class, line, type, markers)`
+    /// Cheaper than dicts and maps directly onto a fixed (e.g. protobuf) schema
+    fn collect_records(&self) -> PyResult<Vec<ItemRecord>> {
+        let records = self.item_records();
+        self.raise_on_parse_error()?;
+        Ok(records)
+    }
 
-        let mut items = Vec::new();
+    /// JUnit XML skeleton (`<testsuite>` with one result-less `<testcase>` per node id)
+    /// for tracking tests that have not run yet
+    #[pyo3(signature = (keyword_expr=None, marker_expr=None))]
+    fn collect_junit_skeleton(&self, keyword_expr: Option<String>, marker_expr: Option<String>) -> PyResult<String> {
+        let filter = TestFilter::new(keyword_expr, marker_expr);
+        let xml = self.junit_skeleton(&filter);
+        self.raise_on_parse_error()?;
+        Ok(xml)
+    }
 
-        for stmt in &module {
-            self.extract_test_items(stmt, &file_path, None, &mut items);
-        }
+    /// TAP plan for the filtered node ids: `1..N` followed by one `# test: <node id>` comment each
+    #[pyo3(signature = (keyword_expr=None, marker_expr=None))]
+    fn collect_tap_plan(&self, keyword_expr: Option<String>, marker_expr: Option<String>) -> PyResult<String> {
+        let filter = TestFilter::new(keyword_expr, marker_expr);
+        let plan = self.tap_plan(&filter);
+        self.raise_on_parse_error()?;
+        Ok(plan)
+    }
 
-        Ok(items)
+    /// GraphViz DOT graph of the suite: directories, files, classes and tests linked by containment
+    fn collect_dot(&self) -> PyResult<String> {
+        let dot = self.dot_graph();
+        self.raise_on_parse_error()?;
+        Ok(dot)
     }
 
-    /// Extract test items from AST nodes
-    fn extract_test_items(
+    /// Number of tests per unique combination of effective markers, keyed by the sorted,
+    /// comma-joined marker names (`"integration,slow"`; unmarked tests under `""`)
+    fn collect_marker_combos(&self) -> PyResult<BTreeMap<String, usize>> {
+        let combos = self.marker_combos();
+        self.raise_on_parse_error()?;
+        Ok(combos)
+    }
+
+    /// Marker names used by the current collection but by no test in the cache file,
+    /// e.g. newly introduced markers or typos (all markers when there is no usable cache)
+    fn new_markers_since_cache(&self) -> PyResult<Vec<String>> {
+        let markers = self.new_markers();
+        self.raise_on_parse_error()?;
+        Ok(markers)
+    }
+
+    /// Node ids generated more than once (redefined tests, repeated parametrize ids)
+    fn check_node_id_uniqueness(&self) -> PyResult<Vec<String>> {
+        let duplicates = self.duplicate_node_ids();
+        self.raise_on_parse_error()?;
+        Ok(duplicates)
+    }
+
+    /// Predict the runtime of the filtered tests from historical `{node id: seconds}` durations
+    /// Tests without history count as `default_duration` (the mean known duration if omitted).
+    #[pyo3(signature = (durations, keyword_expr=None, marker_expr=None, default_duration=None))]
+    fn estimate_runtime(
         &self,
-        stmt: &ast::Stmt,
-        file_path: &str,
-        class_context: Option<&str>,
-        items: &mut Vec<TestItem>,
-    ) {
-        match stmt {
-            ast::Stmt::FunctionDef(func) => {
-                let name = func.name.as_str();
-                if self.is_test_function(name) {
-                    let markers = self.extract_markers(&func.decorator_list);
-                    let parametrize_count = self.extract_parametrize_count(&func.decorator_list);
-                    items.push(TestItem {
-                        file_path: file_path.to_string(),
-                        name: name.to_string(),
-                        line_number: func.range.start().to_u32() as usize,
-                        item_type: if class_context.is_some() {
-                            TestItemType::Method
-                        } else {
-                            TestItemType::Function
-                        },
-                        class_name: class_context.map(|s| s.to_string()),
-                        markers,
-                        parametrize_count,
-                    });
-                }
-            }
-            ast::Stmt::ClassDef(class) => {
-                let class_name = class.name.as_str();
-                if self.is_test_class(class_name) {
-                    let markers = self.extract_markers(&class.decorator_list);
-                    // Add the class itself
-                    items.push(TestItem {
-                        file_path: file_path.to_string(),
-                        name: class_name.to_string(),
-                        line_number: class.range.start().to_u32() as usize,
-                        item_type: TestItemType::Class,
-                        class_name: None,
-                        markers,
-                        parametrize_count: None,
-                    });
+        durations: HashMap<String, f64>,
+        keyword_expr: Option<String>,
+        marker_expr: Option<String>,
+        default_duration: Option<f64>,
+    ) -> PyResult<f64> {
+        let filter = TestFilter::new(keyword_expr, marker_expr);
+        let estimate = self.estimated_runtime(&durations, &filter, default_duration);
+        self.raise_on_parse_error()?;
+        Ok(estimate)
+    }
 
-                    // Extract methods from the class
-                    for stmt in &class.body {
-                        self.extract_test_items(stmt, file_path, Some(class_name), items);
-                    }
-                }
-            }
-            _ => {}
+    /// Raise `SyntaxError` for the first recorded parse error under the "error" policy
+    fn raise_on_parse_error(&self) -> PyResult<()> {
+        match self.fatal_parse_error() {
+            Some(error) => Err(PyErr::new::<pyo3::exceptions::PySyntaxError, _>(format!(
+                "Failed to parse test file {}",
+                error
+            ))),
+            None => Ok(()),
         }
     }
+}
 
-    /// Extract pytest markers from decorator list
-    fn extract_markers(&self, decorators: &[ast::Expr]) -> Vec<String> {
-        let mut markers = Vec::new();
+impl FastCollector {
+    /// Collect file metadata with the filter applied during parallel iteration
+    fn collect_filtered(&self, filter: &TestFilter) -> Vec<FileMetadata> {
+        let filter = &self.configured_filter(filter);
+        let started = Instant::now();
+        self.begin_collection();
+        let file_metadata = match self.git_ref {
+            Some(ref reference) => self.collect_git_filtered(reference, filter),
+            None => self.collect_tree_filtered(filter),
+        };
 
-        for decorator in decorators {
-            // Handle @pytest.mark.marker_name or @mark.marker_name
-            if let ast::Expr::Attribute(attr) = decorator {
-                if let ast::Expr::Attribute(parent_attr) = attr.value.as_ref() {
-                    if let ast::Expr::Name(name) = parent_attr.value.as_ref() {
-                        if name.id.as_str() == "pytest" && parent_attr.attr.as_str() == "mark" {
-                            markers.push(attr.attr.to_string());
-                        }
-                    }
-                } else if let ast::Expr::Name(name) = attr.value.as_ref() {
-                    if name.id.as_str() == "mark" {
-                        markers.push(attr.attr.to_string());
-                    }
-                }
+        let tests = file_metadata
+            .iter()
+            .flat_map(|file_meta| &file_meta.test_items)
+            .filter(|item| !matches!(item.item_type, TestItemType::Class))
+            .count();
+        self.stats.files_collected.store(file_metadata.len(), Ordering::Relaxed);
+        self.stats.tests_collected.store(tests, Ordering::Relaxed);
+        self.stats.duration_micros.store(started.elapsed().as_micros() as usize, Ordering::Relaxed);
+        file_metadata
+    }
+
+    /// Filtered collection of the test files on disk
+    fn collect_tree_filtered(&self, filter: &TestFilter) -> Vec<FileMetadata> {
+        let test_files = self.find_test_files();
+
+        // PHASE 3: Use cache to avoid re-parsing unchanged files
+        // Use rayon for parallel processing WITH caching AND filtering
+        let file_metadata: Vec<FileMetadata> = test_files
+            .par_iter()
+            .with_min_len(self.min_task_len())
+            .filter(|file_path| self.in_scope(file_path, filter))
+            .filter_map(|file_path| self.collect_file_filtered(file_path, filter))
+            .collect();
+
+        self.finish_collection();
+        file_metadata
+    }
+
+    /// Order the reports of a collection, then save the cache
+    fn finish_collection(&self) {
+        self.sort_reports();
+        // PHASE 3: Save cache after collection (non-fatal if it fails)
+        let _ = self.save_cache();
+    }
+
+    /// Parallel iteration pushes reports in arbitrary order
+    fn sort_reports(&self) {
+        self.empty_after_filter.write().unwrap().sort();
+        self.parse_errors.write().unwrap().sort_by(|a, b| a.file_path.cmp(&b.file_path));
+        self.trace.write().unwrap().sort();
+    }
+
+    /// Filtered collection of the test files in the tree at a git ref, parsed from their blobs
+    /// Paths are the tree paths and `mtime` is 0; the file cache is not used.
+    #[cfg(feature = "git")]
+    fn collect_git_filtered(&self, reference: &str, filter: &TestFilter) -> Vec<FileMetadata> {
+        let sources = match self.git_sources(reference) {
+            Ok(sources) => sources,
+            Err(e) => {
+                self.warnings.write().unwrap().push(format!("Cannot read git ref '{}': {}", reference, e.message()));
+                return Vec::new();
             }
-            // Handle @pytest.mark.marker_name(...) or @mark.marker_name(...)
-            else if let ast::Expr::Call(call) = decorator {
-                if let ast::Expr::Attribute(attr) = call.func.as_ref() {
-                    if let ast::Expr::Attribute(parent_attr) = attr.value.as_ref() {
-                        if let ast::Expr::Name(name) = parent_attr.value.as_ref() {
-                            if name.id.as_str() == "pytest" && parent_attr.attr.as_str() == "mark" {
-                                markers.push(attr.attr.to_string());
-                            }
-                        }
-                    } else if let ast::Expr::Name(name) = attr.value.as_ref() {
-                        if name.id.as_str() == "mark" {
-                            markers.push(attr.attr.to_string());
-                        }
-                    }
+        };
+
+        let file_metadata = sources
+            .par_iter()
+            .with_min_len(self.min_task_len())
+            .filter(|source| !self.parse_failed() && filter.matches_path(&source.relative_path))
+            .filter_map(|source| self.apply_filter(self.collect_source(&source.content, &source.tree_path), filter))
+            .collect();
+
+        self.sort_reports();
+        file_metadata
+    }
+
+    /// Without the `git` feature a git ref cannot be read; nothing is collected
+    #[cfg(not(feature = "git"))]
+    fn collect_git_filtered(&self, reference: &str, _filter: &TestFilter) -> Vec<FileMetadata> {
+        self.warnings.write().unwrap().push(format!(
+            "Cannot read git ref '{}': built without the `git` feature",
+            reference
+        ));
+        Vec::new()
+    }
+
+    /// The test files under the root in the tree at `reference`
+    #[cfg(feature = "git")]
+    fn git_sources(&self, reference: &str) -> Result<Vec<GitSource>, git2::Error> {
+        let repo = git2::Repository::discover(&self.root_path)?;
+        let tree = repo.revparse_single(reference)?.peel_to_tree()?;
+        // Position of a directory inside the repository, e.g. `tests` for the root
+        let position = |dir: &Path| match (repo.workdir().map(fs::canonicalize), fs::canonicalize(dir)) {
+            (Some(Ok(workdir)), Ok(dir)) => dir.strip_prefix(workdir).map(Path::to_path_buf).unwrap_or_default(),
+            _ => PathBuf::new(),
+        };
+        let prefix = position(&self.root_path);
+        // Globs are matched relative to the same directory as on the filesystem
+        let glob_base = position(self.rootdir.as_ref().unwrap_or(&self.root_path));
+
+        let mut sources = Vec::new();
+        tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            let path = Path::new(dir).join(entry.name().unwrap_or_default());
+            let related = path.starts_with(&prefix) || prefix.starts_with(&path);
+            if !related || self.should_ignore(&path) {
+                return git2::TreeWalkResult::Skip;
+            }
+            if entry.kind() == Some(git2::ObjectType::Blob) && path.starts_with(&prefix) && self.is_test_file(&path) {
+                if let Ok(blob) = entry.to_object(&repo).and_then(|object| object.peel_to_blob()) {
+                    let tree_path = path.to_string_lossy().replace('\\', "/");
+                    // Blobs are decoded like files on disk
+                    let Some(content) = decode_source(blob.content()) else {
+                        self.warnings.write().unwrap().push(format!("Skipping undecodable test file {}", tree_path));
+                        return git2::TreeWalkResult::Ok;
+                    };
+                    sources.push(GitSource {
+                        relative_path: path.strip_prefix(&glob_base).unwrap_or(&path).to_string_lossy().replace('\\', "/"),
+                        tree_path,
+                        content,
+                    });
                 }
             }
-        }
+            git2::TreeWalkResult::Ok
+        })?;
+        Ok(sources)
+    }
 
-        markers
+    /// The filter with collector-wide filtering settings applied
+    fn configured_filter(&self, filter: &TestFilter) -> TestFilter {
+        TestFilter {
+            inherit_markers: self.inherit_markers,
+            ..filter.clone()
+        }
     }
 
-    /// Extract parametrize count from decorator list
-    /// Parses @pytest.mark.parametrize("arg", [val1, val2, ...]) to count parameter sets
-    /// This allows us to generate the correct number of test nodes WITHOUT importing Python code!
-    fn extract_parametrize_count(&self, decorators: &[ast::Expr]) -> Option<usize> {
-        for decorator in decorators {
-            // Look for @pytest.mark.parametrize(...) or @mark.parametrize(...)
-            if let ast::Expr::Call(call) = decorator {
-                if let ast::Expr::Attribute(attr) = call.func.as_ref() {
-                    let is_parametrize = attr.attr.as_str() == "parametrize";
+    fn rebuild_index(&self) -> usize {
+        let items: Vec<TestItem> = self
+            .collect_filtered(&TestFilter::new(None, None))
+            .into_iter()
+            .flat_map(|file_meta| file_meta.test_items)
+            .collect();
+        let count = items.len();
+        *self.index.write().unwrap() = items;
+        count
+    }
 
-                    if !is_parametrize {
-                        continue;
-                    }
+    fn query_index(&self, filter: &TestFilter) -> Vec<TestItem> {
+        let filter = self.configured_filter(filter);
+        self.index.read().unwrap().iter().filter(|item| filter.matches(item)).cloned().collect()
+    }
 
-                    // Check if it's pytest.mark.parametrize or mark.parametrize
-                    let is_pytest_mark = if let ast::Expr::Attribute(parent_attr) = attr.value.as_ref() {
-                        if let ast::Expr::Name(name) = parent_attr.value.as_ref() {
-                            name.id.as_str() == "pytest" && parent_attr.attr.as_str() == "mark"
-                        } else {
-                            false
-                        }
-                    } else if let ast::Expr::Name(name) = attr.value.as_ref() {
-                        name.id.as_str() == "mark"
-                    } else {
-                        false
-                    };
+    /// Every file with tests, keeping only its class items
+    fn collect_container_metadata(&self) -> Vec<FileMetadata> {
+        let mut file_metadata = self.collect_filtered(&TestFilter::new(None, None));
+        for file_meta in &mut file_metadata {
+            file_meta.test_items.retain(|item| item.item_type == TestItemType::Class);
+        }
+        file_metadata
+    }
 
-                    if !is_pytest_mark {
-                        continue;
-                    }
+    /// Filtered collection that hands each file's metadata to `on_file` and each recorded parse
+    /// error to `on_error` as soon as they are ready
+    /// Results are funneled through a channel so the callbacks run on the calling thread.
+    fn collect_streaming<F, E>(&self, filter: &TestFilter, mut on_file: F, mut on_error: E)
+    where
+        F: FnMut(FileMetadata),
+        E: FnMut(FileParseError),
+    {
+        let filter = &self.configured_filter(filter);
+        self.begin_collection();
+        let test_files = self.find_test_files();
 
-                    // Try to extract the parameter count from the second argument
-                    // @pytest.mark.parametrize("arg", [val1, val2, val3]) -> count = 3
-                    // @pytest.mark.parametrize("arg1,arg2", [(v1,v2), (v3,v4)]) -> count = 2
-                    if call.args.len() >= 2 {
-                        if let ast::Expr::List(list_expr) = &call.args[1] {
-                            return Some(list_expr.elts.len());
-                        } else if let ast::Expr::Tuple(tuple_expr) = &call.args[1] {
-                            return Some(tuple_expr.elts.len());
+        let (sender, receiver) = mpsc::channel();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                test_files
+                    .par_iter()
+                    .with_min_len(self.min_task_len())
+                    .filter(|file_path| self.in_scope(file_path, filter))
+                    .for_each_with(sender, |sender, file_path| {
+                        let (file_meta, error) = self.collect_file_reporting(file_path, file_mtime(file_path), filter);
+                        // The receiver outlives the producers, so sending cannot fail
+                        if let Some(error) = error {
+                            let _ = sender.send(StreamEvent::ParseError(error));
                         }
-                    }
+                        if let Some(file_meta) = file_meta {
+                            let _ = sender.send(StreamEvent::File(file_meta));
+                        }
+                    });
+            });
+
+            for event in receiver {
+                match event {
+                    StreamEvent::File(file_meta) => on_file(file_meta),
+                    StreamEvent::ParseError(error) => on_error(error),
                 }
             }
-        }
+        });
 
-        None
+        self.finish_collection();
     }
 
-    /// Check if a function name indicates a test function
-    fn is_test_function(&self, name: &str) -> bool {
-        name.starts_with("test_") || name.starts_with("test")
+    /// Whether a discovered file is in scope of the filter's path globs
+    fn in_scope(&self, file_path: &Path, filter: &TestFilter) -> bool {
+        let relative = self.relative_path(file_path);
+        let in_scope = filter.matches_path(&relative);
+        self.trace(|| match in_scope {
+            true => format!("{}: discovered", relative),
+            false => format!("{}: discovered, outside the include/exclude globs", relative),
+        });
+        in_scope
     }
 
-    /// Check if a class name indicates a test class
-    fn is_test_class(&self, name: &str) -> bool {
-        name.starts_with("Test")
+    /// Add a line to the collection trace (the message is only built when tracing)
+    fn trace<F: FnOnce() -> String>(&self, message: F) {
+        if self.trace_enabled {
+            self.trace.write().unwrap().push(message());
+        }
     }
 
-    /// Convert test items to Python dict structure with rich metadata
-    fn items_to_python(&self, py: Python, items: &[TestItem]) -> PyResult<Py<PyAny>> {
-        let result = PyDict::new(py);
+    /// Reset the per-collection reports (empty files, parse errors, warnings)
+    fn begin_collection(&self) {
+        self.empty_after_filter.write().unwrap().clear();
+        self.parse_errors.write().unwrap().clear();
+        self.warnings.write().unwrap().clear();
+        self.trace.write().unwrap().clear();
+        self.stats.reset();
+    }
 
-        // Group by file
-        let mut files_map: HashMap<String, Vec<&TestItem>> = HashMap::new();
-        for item in items {
-            files_map.entry(item.file_path.clone())
-                .or_insert_with(Vec::new)
-                .push(item);
+    /// Report a file's extraction warnings and record its parse error according to the
+    /// configured policy
+    fn record_parse_outcome(&self, parsed: &ParsedFile) {
+        if !parsed.warnings.is_empty() {
+            self.warnings.write().unwrap().extend(parsed.warnings.iter().cloned());
+        }
+        let Some(ref error) = parsed.parse_error else {
+            return;
+        };
+        match self.parse_error_policy {
+            ParseErrorPolicy::Skip => {}
+            ParseErrorPolicy::Warn => {
+                self.warnings.write().unwrap().push(format!("Skipping unparseable test file {}", error));
+                self.parse_errors.write().unwrap().push(error.clone());
+            }
+            ParseErrorPolicy::Error => {
+                self.parse_errors.write().unwrap().push(error.clone());
+            }
         }
+    }
 
-        for (file_path, file_items) in files_map {
-            let items_list = PyList::empty(py);
+    /// The parse error that fails the collection under the "error" policy, if one was recorded
+    fn fatal_parse_error(&self) -> Option<FileParseError> {
+        if self.parse_error_policy != ParseErrorPolicy::Error {
+            return None;
+        }
+        self.parse_errors.read().unwrap().first().cloned()
+    }
 
-            for item in file_items {
-                let item_dict = PyDict::new(py);
-                item_dict.set_item("name", &item.name)?;
-                item_dict.set_item("line", item.line_number)?;
-                item_dict.set_item("type", format!("{:?}", item.item_type))?;
-                item_dict.set_item("file_path", &item.file_path)?;
-
-                if let Some(ref class_name) = item.class_name {
-                    item_dict.set_item("class", class_name)?;
-                }
+    /// Whether the "error" policy already failed this collection, so remaining files are skipped
+    fn parse_failed(&self) -> bool {
+        self.parse_error_policy == ParseErrorPolicy::Error && !self.parse_errors.read().unwrap().is_empty()
+    }
 
-                // Add markers
-                let markers_list = PyList::empty(py);
-                for marker in &item.markers {
-                    markers_list.append(marker)?;
-                }
-                item_dict.set_item("markers", markers_list)?;
+    /// Parse a file without the cache, recording any parse error
+    fn parse_file_recorded(&self, path: &Path) -> ParsedFile {
+        if self.parse_failed() {
+            return ParsedFile::default();
+        }
+        self.stats.files_parsed.fetch_add(1, Ordering::Relaxed);
+        let parsed = self.parse_file(path).unwrap_or_default();
+        self.record_parse_outcome(&parsed);
+        parsed
+    }
 
-                // Add parametrize count
-                if let Some(count) = item.parametrize_count {
-                    item_dict.set_item("parametrize_count", count)?;
-                }
+    /// Extract items from in-memory source; there is no file, so `mtime` is 0
+    fn collect_source(&self, content: &str, virtual_path: &str) -> FileMetadata {
+        self.stats.files_parsed.fetch_add(1, Ordering::Relaxed);
+        let parsed = self.parse_source(content, virtual_path);
+        self.record_parse_outcome(&parsed);
+        FileMetadata::new(virtual_path.to_string(), 0.0, parsed)
+    }
 
-                items_list.append(item_dict)?;
+    /// Replace the `tests` table of a SQLite database with the given items
+    fn write_sqlite(&self, db_path: &Path, file_metadata: &[FileMetadata]) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut conn = rusqlite::Connection::open(db_path)?;
+        let tx = conn.transaction()?;
+        tx.execute_batch(
+            "DROP TABLE IF EXISTS tests;
+             CREATE TABLE tests (
+                 file TEXT NOT NULL,
+                 class TEXT,
+                 name TEXT NOT NULL,
+                 line INTEGER NOT NULL,
+                 type TEXT NOT NULL,
+                 markers TEXT NOT NULL,
+                 node_id TEXT NOT NULL
+             );",
+        )?;
+
+        let mut rows = 0;
+        {
+            let mut insert = tx.prepare("INSERT INTO tests VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+            for item in file_metadata.iter().flat_map(|file_meta| &file_meta.test_items) {
+                insert.execute(rusqlite::params![
+                    item.file_path,
+                    item.class_name,
+                    item.name,
+                    item.line_number as i64,
+                    format!("{:?}", item.item_type),
+                    serde_json::to_string(&item.markers)?,
+                    self.node_id(item),
+                ])?;
+                rows += 1;
             }
-
-            result.set_item(file_path, items_list)?;
         }
-
-        Ok(result.into())
+        tx.commit()?;
+        Ok(rows)
     }
 
-    /// Convert file metadata to Python dict structure
-    fn metadata_to_python(&self, py: Python, metadata: &[FileMetadata]) -> PyResult<Py<PyAny>> {
-        let result = PyDict::new(py);
+    /// Metadata of the changed files that still exist, served from the cache only when unchanged
+    /// A file keeps its entry when its mtime did not move or, within `min_interval` of the cached
+    /// mtime and with a hash algorithm set, its content hash still matches. Later saves re-parse
+    /// without hashing.
+    fn collect_changed_files(&self, paths: &[PathBuf], min_interval: f64) -> Vec<FileMetadata> {
+        let file_metadata = paths
+            .par_iter()
+            .with_min_len(self.min_task_len())
+            .filter(|path| path.is_file())
+            .filter_map(|path| {
+                let path_str = path.to_string_lossy().to_string();
+                let mtime = file_mtime(path);
+                let mut cache = self.cache.write().unwrap();
+                if cache
+                    .get(&path_str)
+                    .is_some_and(|entry| mtime - entry.mtime > min_interval + MTIME_TOLERANCE_SECONDS)
+                {
+                    cache.remove(&path_str);
+                }
+                drop(cache);
+                self.collect_file_reporting(path, mtime, &TestFilter::new(None, None)).0
+            })
+            .collect();
 
-        for file_meta in metadata {
-            let file_dict = PyDict::new(py);
-            file_dict.set_item("mtime", file_meta.mtime)?;
+        self.finish_collection();
+        file_metadata
+    }
 
-            let items_list = PyList::empty(py);
-            for item in &file_meta.test_items {
-                let item_dict = PyDict::new(py);
-                item_dict.set_item("name", &item.name)?;
-                item_dict.set_item("line", item.line_number)?;
-                item_dict.set_item("type", format!("{:?}", item.item_type))?;
-                item_dict.set_item("file_path", &item.file_path)?;
-
-                if let Some(ref class_name) = item.class_name {
-                    item_dict.set_item("class", class_name)?;
-                }
+    /// Files with caller-supplied mtimes, parsed through the cache
+    fn collect_with_mtimes(&self, files: &[(PathBuf, f64)], filter: &TestFilter) -> Vec<FileMetadata> {
+        let file_metadata = files
+            .par_iter()
+            .filter(|(path, _)| self.in_scope(path, filter))
+            .filter_map(|(path, mtime)| self.collect_file_reporting(path, *mtime, filter).0)
+            .collect();
 
-                // Add markers
-                let markers_list = PyList::empty(py);
-                for marker in &item.markers {
-                    markers_list.append(marker)?;
-                }
-                item_dict.set_item("markers", markers_list)?;
+        self.finish_collection();
+        file_metadata
+    }
 
-                // Add parametrize count
-                if let Some(count) = item.parametrize_count {
-                    item_dict.set_item("parametrize_count", count)?;
-                }
+    /// Test files of a directory (its whole subtree when `recursive`), parsed through the cache
+    fn collect_directory(&self, dir: &Path, recursive: bool) -> Vec<FileMetadata> {
+        let mut test_files: Vec<PathBuf> = if recursive {
+            WalkDir::new(dir)
+                .into_iter()
+                .filter_entry(|e| !self.should_ignore(e.path()))
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file() && self.is_test_file(e.path()))
+                .map(|e| e.path().to_path_buf())
+                .collect()
+        } else {
+            fs::read_dir(dir)
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_ok_and(|file_type| file_type.is_file()))
+                .map(|e| e.path())
+                .filter(|path| !self.should_ignore(path) && self.is_test_file(path))
+                .collect()
+        };
+        test_files.sort();
 
-                items_list.append(item_dict)?;
-            }
-            file_dict.set_item("items", items_list)?;
+        let file_metadata = test_files
+            .par_iter()
+            .with_min_len(self.min_task_len())
+            .filter_map(|file_path| self.collect_file_filtered(file_path, &TestFilter::new(None, None)))
+            .collect();
 
-            result.set_item(&file_meta.path, file_dict)?;
-        }
+        self.finish_collection();
+        file_metadata
+    }
 
-        Ok(result.into())
+    /// Collect the items whose node id starts with any of `prefixes` at a node boundary
+    fn collect_by_prefix(&self, prefixes: &[String]) -> Vec<FileMetadata> {
+        self.collect_filtered(&TestFilter::new(None, None))
+            .into_iter()
+            .filter_map(|mut file_meta| {
+                file_meta.test_items.retain(|item| {
+                    let node_id = self.node_id(item);
+                    prefixes.iter().any(|prefix| self.node_id_has_prefix(&node_id, prefix))
+                });
+                (!file_meta.test_items.is_empty()).then_some(file_meta)
+            })
+            .collect()
     }
-}
+
+    /// Whether `prefix` names `node_id` or one of its ancestors: the match must end the id or
+    /// be followed by a separator, a `/` or a `[` parametrize suffix, so `TestAuth` does not
+    /// select `TestAuthz`
+    fn node_id_has_prefix(&self, node_id: &str, prefix: &str) -> bool {
+        let separator = self.node_id_separator.as_str();
+        let Some(rest) = node_id.strip_prefix(prefix) else {
+            return false;
+        };
+        rest.is_empty()
+            || rest.starts_with(separator)
+            || rest.starts_with(['/', '['])
+            || prefix.ends_with(separator)
+            || prefix.ends_with('/')
+    }
+
+    /// Parse the allowlisted files, keeping items whose name or qualname is listed for their file
+    fn collect_allowlisted(&self, allow: &HashMap<String, Vec<String>>) -> Vec<FileMetadata> {
+        let base = self.rootdir.as_ref().unwrap_or(&self.root_path);
+        let mut allowed: Vec<(PathBuf, &Vec<String>)> = allow
+            .iter()
+            .map(|(relative, names)| (base.join(relative), names))
+            .filter(|(path, _)| path.is_file())
+            .collect();
+        allowed.sort();
+
+        let file_metadata = allowed
+            .par_iter()
+            .with_min_len(self.min_task_len())
+            .filter_map(|(path, names)| {
+                let mut file_meta = self.collect_file_filtered(path, &TestFilter::new(None, None))?;
+                file_meta
+                    .test_items
+                    .retain(|item| names.contains(&item.name) || names.contains(&item.qualname));
+                (!file_meta.test_items.is_empty()).then_some(file_meta)
+            })
+            .collect();
+
+        self.finish_collection();
+        file_metadata
+    }
+
+    /// Parse a file keeping only the items overlapping the given line range (IDE "run selection")
+    fn collect_lines(&self, path: &Path, start_line: usize, end_line: usize) -> FileMetadata {
+        let mut file_metadata = FileMetadata::new(
+            path.to_string_lossy().to_string(),
+            file_mtime(path),
+            self.parse_file_recorded(path),
+        );
+        file_metadata
+            .test_items
+            .retain(|item| item.line_number <= end_line && item.end_line >= start_line);
+        file_metadata
+    }
+
+    /// Map a dotted module path to an existing `.py` file under the root
+    fn resolve_module(&self, dotted: &str) -> Option<PathBuf> {
+        let components: Vec<&str> = dotted.split('.').collect();
+        let valid = components.iter().all(|component| {
+            !component.is_empty() && component.chars().all(|c| c == '_' || c.is_alphanumeric())
+        });
+        if !valid {
+            return None;
+        }
+
+        let mut path = self.root_path.clone();
+        path.extend(&components);
+        path.set_extension("py");
+        path.is_file().then_some(path)
+    }
+
+    /// Metadata of a module resolved by `resolve_module`, served from the file cache when valid
+    fn collect_module_file(&self, path: &Path) -> FileMetadata {
+        let mtime = file_mtime(path);
+        let parsed = self.parse_file_cached(path, mtime);
+        self.record_parse_outcome(&parsed);
+        let _ = self.save_cache();
+        FileMetadata::new(path.to_string_lossy().to_string(), mtime, parsed)
+    }
+
+    /// Every collected item as a flat record, in collection order
+    fn item_records(&self) -> Vec<ItemRecord> {
+        self.collect_filtered(&TestFilter::new(None, None))
+            .iter()
+            .flat_map(|file_meta| &file_meta.test_items)
+            .map(TestItem::to_record)
+            .collect()
+    }
+
+    /// Minimum Rayon task length derived from the configured chunk size
+    fn min_task_len(&self) -> usize {
+        self.parallel_chunk_size.max(1)
+    }
+
+    /// Node ids of the runnable items (functions and methods) left after filtering
+    fn filtered_node_ids(&self, filter: &TestFilter) -> Vec<String> {
+        let filter = &self.configured_filter(filter);
+        let mut node_ids: Vec<String> = self
+            .collect_filtered(filter)
+            .iter()
+            .flat_map(|file_meta| &file_meta.test_items)
+            .filter(|item| !matches!(item.item_type, TestItemType::Class))
+            .flat_map(|item| {
+                if item.parametrize_cases.is_empty() {
+                    return vec![self.node_id(item)];
+                }
+                // Expand into one node id per case, filtering each with its own marks
+                item.case_items()
+                    .iter()
+                    .filter(|case| filter.matches(case))
+                    .map(|case| self.node_id(case))
+                    .collect()
+            })
+            .collect();
+        if let Some(seed) = self.shuffle_seed {
+            shuffle_seeded(&mut node_ids, seed);
+        }
+        node_ids
+    }
+
+    /// `filtered_node_ids` through the result cache
+    /// Results of collections with parse errors are not kept, so the errors are reported again.
+    fn cached_node_ids(&self, filter: &TestFilter) -> Vec<String> {
+        let key = (filter.keyword_expr.clone(), filter.marker_expr.clone());
+        self.begin_collection();
+        let fingerprint = self.result_fingerprint();
+        if let Some((cached_fingerprint, node_ids)) = self.result_cache.read().unwrap().get(&key) {
+            if *cached_fingerprint == fingerprint {
+                self.stats.result_cache_hits.fetch_add(1, Ordering::Relaxed);
+                return node_ids.clone();
+            }
+        }
+
+        let node_ids = self.filtered_node_ids(filter);
+        if self.parse_errors.read().unwrap().is_empty() {
+            self.result_cache.write().unwrap().insert(key, (fingerprint, node_ids.clone()));
+        }
+        node_ids
+    }
+
+    /// Parse and filter settings plus every test file with its mtime
+    fn result_fingerprint(&self) -> String {
+        let mut fingerprint = format!(
+            "{};inherit_markers={};node_id_separator={};node_id_prefix={};shuffle_seed={:?}",
+            self.parse_options_key(),
+            self.inherit_markers,
+            self.node_id_separator,
+            self.node_id_prefix,
+            self.shuffle_seed
+        );
+        let mut test_files = self.find_test_files();
+        test_files.sort();
+        for path in test_files {
+            fingerprint.push_str(&format!("\n{}:{}", path.display(), file_mtime(&path)));
+        }
+        fingerprint
+    }
+
+    /// Digest over the sorted node ids of the runnable items (one per known parametrize case),
+    /// each followed by its source lines when `include_bodies` is set
+    fn suite_digest(&self, filter: &TestFilter, include_bodies: bool) -> String {
+        let filter = &self.configured_filter(filter);
+        let mut entries: Vec<(String, &str)> = Vec::new();
+        let file_metadata = self.collect_filtered(filter);
+        let contents: Vec<String> = file_metadata
+            .iter()
+            .map(|file_meta| match include_bodies {
+                true => fs::read_to_string(&file_meta.path).unwrap_or_default(),
+                false => String::new(),
+            })
+            .collect();
+
+        for (file_meta, content) in file_metadata.iter().zip(&contents) {
+            let lines: Vec<&str> = content.split_inclusive('\n').collect();
+            for item in file_meta.test_items.iter().filter(|item| item.item_type != TestItemType::Class) {
+                // Byte range of the item's lines within the file
+                let start: usize = lines.iter().take(item.line_number.saturating_sub(1)).map(|line| line.len()).sum();
+                let end: usize = lines.iter().take(item.end_line).map(|line| line.len()).sum();
+                let body = content.get(start..end).unwrap_or_default();
+
+                if item.parametrize_cases.is_empty() {
+                    entries.push((self.node_id(item), body));
+                } else {
+                    for case in item.case_items().iter().filter(|case| filter.matches(case)) {
+                        entries.push((self.node_id(case), body));
+                    }
+                }
+            }
+        }
+        entries.sort();
+
+        let mut buffer = Vec::new();
+        for (node_id, body) in entries {
+            buffer.extend_from_slice(node_id.as_bytes());
+            buffer.push(b'\n');
+            buffer.extend_from_slice(body.as_bytes());
+            buffer.push(0);
+        }
+        self.hash_algorithm.unwrap_or(HashAlgorithm::Blake3).digest(&buffer)
+    }
+
+    fn missing_required_markers(&self, required_any_of: &[String]) -> Vec<String> {
+        self.collect_filtered(&TestFilter::new(None, None))
+            .iter()
+            .flat_map(|file_meta| &file_meta.test_items)
+            .filter(|item| item.item_type != TestItemType::Class)
+            .flat_map(|item| match item.parametrize_cases.is_empty() {
+                true => vec![item.clone()],
+                false => item.case_items(),
+            })
+            .filter(|item| !item.effective_markers().any(|marker| required_any_of.contains(marker)))
+            .map(|item| self.node_id(&item))
+            .collect()
+    }
+
+    fn item_objects(&self, filter: &TestFilter) -> Vec<CollectedItem> {
+        self.collect_filtered(filter)
+            .into_iter()
+            .flat_map(|file_meta| file_meta.test_items)
+            .map(|item| CollectedItem { node_id: self.node_id(&item), item })
+            .collect()
+    }
+
+    fn coverage_targets(&self) -> BTreeMap<String, Vec<String>> {
+        let mut targets: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for file_meta in self.collect_filtered(&TestFilter::new(None, None)) {
+            let stem = Path::new(&file_meta.path).file_stem().unwrap_or_default().to_string_lossy().to_string();
+            let module = stem
+                .strip_prefix("test_")
+                .or_else(|| stem.strip_suffix("_test"))
+                .unwrap_or(&stem)
+                .to_string();
+            let node_ids = file_meta
+                .test_items
+                .iter()
+                .filter(|item| item.item_type != TestItemType::Class)
+                .map(|item| self.node_id(item));
+            targets.entry(module).or_default().extend(node_ids);
+        }
+        for node_ids in targets.values_mut() {
+            node_ids.sort();
+        }
+        targets
+    }
+
+    /// Node ids of the functions and methods carrying each effective marker
+    fn marker_index(&self, file_metadata: &[FileMetadata]) -> MarkerIndex {
+        let mut index = MarkerIndex::new();
+        for item in file_metadata
+            .iter()
+            .flat_map(|file_meta| &file_meta.test_items)
+            .filter(|item| item.item_type != TestItemType::Class)
+        {
+            let cases = match item.parametrize_cases.is_empty() {
+                true => vec![item.clone()],
+                false => item.case_items(),
+            };
+            for case in &cases {
+                let node_id = self.node_id(case);
+                let markers: BTreeSet<&String> = case.effective_markers().collect();
+                for marker in markers {
+                    index.entry(marker.clone()).or_default().push(node_id.clone());
+                }
+            }
+        }
+        index
+    }
+
+    fn unused_markers(&self) -> Vec<String> {
+        let mut unused: BTreeSet<&String> = self.registered_markers.iter().collect();
+        for item in self
+            .collect_filtered(&TestFilter::new(None, None))
+            .iter()
+            .flat_map(|file_meta| &file_meta.test_items)
+        {
+            for marker in item.effective_markers() {
+                unused.remove(marker);
+            }
+            for case in &item.parametrize_cases {
+                for marker in &case.marks {
+                    unused.remove(marker);
+                }
+            }
+        }
+        unused.into_iter().cloned().collect()
+    }
+
+    fn type_counts(&self, filter: &TestFilter) -> (usize, usize, usize) {
+        let mut counts = (0, 0, 0);
+        for item in self.collect_filtered(filter).iter().flat_map(|file_meta| &file_meta.test_items) {
+            match item.item_type {
+                TestItemType::Function => counts.0 += 1,
+                TestItemType::Class => counts.1 += 1,
+                TestItemType::Method => counts.2 += 1,
+            }
+        }
+        counts
+    }
+
+    /// Own markers of every item that are neither registered nor builtin
+    fn unknown_markers(&self) -> Vec<(String, String)> {
+        self.collect_filtered(&TestFilter::new(None, None))
+            .iter()
+            .flat_map(|file_meta| &file_meta.test_items)
+            .flat_map(|item| item.markers.iter().map(move |marker| (item, marker)))
+            .filter(|(_, marker)| {
+                !self.registered_markers.contains(marker.as_str()) && !BUILTIN_MARKERS.contains(&marker.as_str())
+            })
+            .map(|(item, marker)| (self.node_id(item), marker.clone()))
+            .collect()
+    }
+
+    /// Render the filtered node ids as JUnit `<testcase>` elements, with pytest's
+    /// `classname` convention (`tests.test_api.TestAPI`)
+    fn junit_skeleton(&self, filter: &TestFilter) -> String {
+        let node_ids = self.filtered_node_ids(filter);
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str(&format!("<testsuites>\n  <testsuite name=\"pytest\" tests=\"{}\">\n", node_ids.len()));
+
+        for node_id in &node_ids {
+            let mut parts: Vec<&str> = node_id.split(self.node_id_separator.as_str()).collect();
+            let name = parts.pop().unwrap_or_default();
+            let file = parts.first().copied().unwrap_or_default();
+            let module = file.strip_suffix(".py").unwrap_or(file).replace('/', ".");
+            let classname = std::iter::once(module.as_str())
+                .chain(parts.iter().skip(1).copied())
+                .collect::<Vec<_>>()
+                .join(".");
+            xml.push_str(&format!(
+                "    <testcase classname=\"{}\" name=\"{}\" file=\"{}\" />\n",
+                xml_escape(&classname),
+                xml_escape(name),
+                xml_escape(file)
+            ));
+        }
+
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+
+    fn suspicious_files(&self) -> Vec<String> {
+        let candidates: Vec<PathBuf> = self
+            .walk_roots()
+            .into_iter()
+            .flat_map(|root| {
+                WalkDir::new(root)
+                    .into_iter()
+                    .filter_entry(|e| !self.should_ignore(e.path()))
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_file() && e.file_name() != "conftest.py")
+                    .filter(|e| e.path().extension().is_some_and(|ext| ext == "py") && !self.is_test_file(e.path()))
+                    .map(|e| e.path().to_path_buf())
+            })
+            .collect();
+
+        let mut suspicious: Vec<String> = candidates
+            .par_iter()
+            .filter(|path| {
+                let Ok(content) = fs::read_to_string(path) else {
+                    return false;
+                };
+                let Ok(module) = ast::Suite::parse(&content, &path.to_string_lossy()) else {
+                    return false;
+                };
+                module
+                    .iter()
+                    .filter_map(FunctionDef::from_stmt)
+                    .any(|func| self.is_test_function(&normalize_identifier(func.name)) && self.fixture_name(&func).is_none())
+            })
+            .map(|path| self.relative_path(path))
+            .collect();
+        suspicious.sort();
+        suspicious
+    }
+
+    fn test_file_directories(&self) -> Vec<String> {
+        let directories: BTreeSet<String> = self
+            .find_test_files()
+            .iter()
+            .filter_map(|path| path.parent())
+            .map(|dir| match self.relative_path(dir) {
+                relative if relative.is_empty() => ".".to_string(),
+                relative => relative,
+            })
+            .collect();
+        directories.into_iter().collect()
+    }
+
+    fn node_ids_importing(&self, module: &str) -> Vec<String> {
+        let submodule_prefix = format!("{}.", module);
+        self.collect_filtered(&TestFilter::new(None, None))
+            .iter()
+            .filter(|file_meta| {
+                file_meta.imports.iter().any(|import| import == module || import.starts_with(&submodule_prefix))
+            })
+            .flat_map(|file_meta| &file_meta.test_items)
+            .filter(|item| !matches!(item.item_type, TestItemType::Class))
+            .map(|item| self.node_id(item))
+            .collect()
+    }
+
+    fn prometheus_metrics(&self) -> String {
+        let metrics = [
+            // Each collection resets these, so they are gauges rather than counters
+            ("fastcollect_files", "gauge", "Test files collected by the last collection", self.stats.files_collected.load(Ordering::Relaxed).to_string()),
+            ("fastcollect_tests", "gauge", "Tests collected by the last collection", self.stats.tests_collected.load(Ordering::Relaxed).to_string()),
+            ("fastcollect_cache_hits", "gauge", "Files served from the cache by the last collection", self.stats.cache_hits.load(Ordering::Relaxed).to_string()),
+            ("fastcollect_parse_errors", "gauge", "Test files with syntax errors in the last collection", self.parse_errors.read().unwrap().len().to_string()),
+            (
+                "fastcollect_collection_duration_seconds",
+                "gauge",
+                "Duration of the last collection",
+                (self.stats.duration_micros.load(Ordering::Relaxed) as f64 / 1e6).to_string(),
+            ),
+        ];
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics {
+            text.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n{} {}\n", name, help, name, kind, name, value));
+        }
+        text
+    }
+
+    fn tap_plan(&self, filter: &TestFilter) -> String {
+        let node_ids = self.filtered_node_ids(filter);
+        let mut plan = format!("1..{}\n", node_ids.len());
+        for node_id in &node_ids {
+            plan.push_str(&format!("# test: {}\n", node_id));
+        }
+        plan
+    }
+
+    /// Build the containment graph from node ids; graph nodes are named by node id
+    /// (`tests`, `tests/test_api.py`, `tests/test_api.py::TestAPI`, ...)
+    fn dot_graph(&self) -> String {
+        let mut nodes: BTreeMap<String, &str> = BTreeMap::new();
+        let mut edges: BTreeSet<(String, String)> = BTreeSet::new();
+
+        for file_meta in self.collect_filtered(&TestFilter::new(None, None)) {
+            for item in &file_meta.test_items {
+                let node_id = self.node_id(item);
+                let shape = match item.item_type {
+                    TestItemType::Class => "box",
+                    _ => "ellipse",
+                };
+                nodes.insert(node_id.clone(), shape);
+
+                // Walk up: classes/tests to their parent via `::`, files and directories via `/`
+                let separator = self.node_id_separator.as_str();
+                let mut child = node_id;
+                while let Some((parent, _)) = child.rsplit_once(separator).or_else(|| child.rsplit_once('/')) {
+                    let shape = if parent.contains(separator) {
+                        "box"
+                    } else if parent.ends_with(".py") {
+                        "note"
+                    } else {
+                        "folder"
+                    };
+                    nodes.entry(parent.to_string()).or_insert(shape);
+                    edges.insert((parent.to_string(), child.clone()));
+                    child = parent.to_string();
+                }
+            }
+        }
+
+        let mut dot = String::from("digraph tests {\n  rankdir=LR;\n");
+        for (node, shape) in &nodes {
+            let label = node.rsplit(self.node_id_separator.as_str()).next().unwrap_or(node);
+            let label = label.rsplit('/').next().unwrap_or(label);
+            dot.push_str(&format!("  {} [label={}, shape={}];\n", dot_quote(node), dot_quote(label), shape));
+        }
+        for (parent, child) in &edges {
+            dot.push_str(&format!("  {} -> {};\n", dot_quote(parent), dot_quote(child)));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Sorted node ids that occur more than once in the full collection
+    fn duplicate_node_ids(&self) -> Vec<String> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for node_id in self.filtered_node_ids(&TestFilter::new(None, None)) {
+            *counts.entry(node_id).or_insert(0) += 1;
+        }
+        counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(node_id, _)| node_id)
+            .collect()
+    }
+
+    /// Markers on the collected tests that no test in the cache file carries
+    fn new_markers(&self) -> Vec<String> {
+        // Read before collecting, which saves the cache
+        let cached: HashSet<String> = self
+            .on_disk_cache()
+            .map(|cache_data| {
+                cache_data
+                    .entries
+                    .into_values()
+                    .flat_map(|entry| entry.parsed.items)
+                    .flat_map(|item| item.markers.into_iter().chain(item.inherited_markers))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let current: BTreeSet<String> = self
+            .collect_filtered(&TestFilter::new(None, None))
+            .iter()
+            .flat_map(|file_meta| &file_meta.test_items)
+            .flat_map(|item| item.effective_markers())
+            .filter(|marker| !cached.contains(marker.as_str()))
+            .cloned()
+            .collect();
+        current.into_iter().collect()
+    }
+
+    /// Count runnable items by their canonical marker combination
+    fn marker_combos(&self) -> BTreeMap<String, usize> {
+        let mut combos = BTreeMap::new();
+        for file_meta in self.collect_filtered(&TestFilter::new(None, None)) {
+            for item in file_meta.test_items.iter().filter(|item| item.item_type != TestItemType::Class) {
+                let markers: BTreeSet<&str> = item.effective_markers().map(String::as_str).collect();
+                let key = markers.into_iter().collect::<Vec<_>>().join(",");
+                *combos.entry(key).or_insert(0) += 1;
+            }
+        }
+        combos
+    }
+
+    /// Sum of historical durations over the filtered node ids
+    fn estimated_runtime(&self, durations: &HashMap<String, f64>, filter: &TestFilter, default_duration: Option<f64>) -> f64 {
+        let default_duration = default_duration.unwrap_or_else(|| {
+            if durations.is_empty() {
+                0.0
+            } else {
+                durations.values().sum::<f64>() / durations.len() as f64
+            }
+        });
+        self.filtered_node_ids(filter)
+            .iter()
+            .map(|node_id| durations.get(node_id).copied().unwrap_or(default_duration))
+            .sum()
+    }
+
+    /// Resolve a node id to its item (or parametrized case) by parsing the referenced file
+    fn find_node(&self, node_id: &str) -> Option<TestItem> {
+        let (relative, _) = node_id.split_once(self.node_id_separator.as_str())?;
+        let relative = match self.node_id_prefix.is_empty() {
+            true => relative,
+            false => relative.strip_prefix(&self.node_id_prefix)?.strip_prefix('/')?,
+        };
+        let path = self.rootdir.as_ref().unwrap_or(&self.root_path).join(relative);
+        if !path.is_file() {
+            return None;
+        }
+
+        self.parse_file_recorded(&path)
+            .items
+            .into_iter()
+            .flat_map(|item| {
+                let cases = item.case_items();
+                std::iter::once(item).chain(cases)
+            })
+            .find(|item| self.node_id(item) == node_id)
+    }
+
+    /// Path relative to the rootdir (or root) with forward slashes, as pytest always uses in node ids
+    fn relative_path(&self, path: &Path) -> String {
+        let relative = path
+            .strip_prefix(self.rootdir.as_ref().unwrap_or(&self.root_path))
+            .unwrap_or(path);
+        relative.to_string_lossy().replace('\\', "/")
+    }
+
+    /// Build a pytest node id (`path/to/test_file.py::Class::test_name`) relative to the root
+    /// Parts are joined with the configured separator.
+    fn node_id(&self, item: &TestItem) -> String {
+        let separator = self.node_id_separator.as_str();
+        let mut node_id = match self.node_id_prefix.is_empty() {
+            true => self.relative_path(Path::new(&item.file_path)),
+            false => format!("{}/{}", self.node_id_prefix, self.relative_path(Path::new(&item.file_path))),
+        };
+
+        if let Some(ref class_name) = item.class_name {
+            node_id.push_str(separator);
+            // Nested class paths are stored `::`-joined
+            node_id.push_str(&class_name.replace("::", separator));
+        }
+        node_id.push_str(separator);
+        node_id.push_str(&item.name);
+        node_id
+    }
+
+    /// Parse (or fetch from cache) one file and apply the filter
+    /// Returns `None` when no item of the file passes the filter
+    fn collect_file_filtered(&self, file_path: &Path, filter: &TestFilter) -> Option<FileMetadata> {
+        self.collect_file_reporting(file_path, file_mtime(file_path), filter).0
+    }
+
+    /// Like `collect_file_filtered` for a file with a known mtime, also returning the file's
+    /// parse error when the policy records it
+    fn collect_file_reporting(
+        &self,
+        file_path: &Path,
+        mtime: f64,
+        filter: &TestFilter,
+    ) -> (Option<FileMetadata>, Option<FileParseError>) {
+        if self.parse_failed() {
+            return (None, None);
+        }
+        let parsed = self.parse_file_cached(file_path, mtime);
+        self.record_parse_outcome(&parsed);
+        let error = parsed.parse_error.clone().filter(|_| self.parse_error_policy != ParseErrorPolicy::Skip);
+
+        let mut file_meta = FileMetadata::new(file_path.to_string_lossy().to_string(), mtime, parsed);
+        file_meta.applicable_conftests = self.applicable_conftests(file_path);
+        (self.apply_filter(file_meta, filter), error)
+    }
+
+    /// Parsed content of a file with a known mtime, from the cache when still valid
+    /// A miss parses the file and stores the result in the cache.
+    fn parse_file_cached(&self, file_path: &Path, mtime: f64) -> ParsedFile {
+        let file_path_str = file_path.to_string_lossy().to_string();
+
+        // PHASE 3: Try to get items from cache first
+        if let Some(cached) = self.get_cached(&file_path_str, mtime) {
+            // Cache hit! Use cached items (avoids AST parsing)
+            self.stats.cache_hits.fetch_add(1, Ordering::Relaxed);
+            self.trace(|| format!("{}: served from cache", self.relative_path(file_path)));
+            cached
+        } else {
+            // Cache miss - parse file and update cache
+            self.stats.files_parsed.fetch_add(1, Ordering::Relaxed);
+            self.trace(|| format!("{}: parsed", self.relative_path(file_path)));
+            // The bytes read for parsing are also the ones hashed
+            let bytes = fs::read(file_path).ok();
+            let parsed = match bytes {
+                Some(ref bytes) => self.parse_bytes(file_path, bytes).unwrap_or_default(),
+                None => ParsedFile::default(),
+            };
+            let entry = CacheEntry {
+                mtime,
+                content_hash: bytes.as_ref().zip(self.hash_algorithm).map(|(bytes, algorithm)| algorithm.digest(bytes)),
+                parsed: parsed.clone(),
+            };
+            self.update_cache(file_path_str, entry);
+            parsed
+        }
+    }
+
+    /// Keep the items of a file that pass the filter
+    /// Returns `None` when none does
+    fn apply_filter(&self, mut file_meta: FileMetadata, filter: &TestFilter) -> Option<FileMetadata> {
+        let had_items = !file_meta.test_items.is_empty();
+        for item in file_meta.test_items.iter().filter(|item| !matches!(item.item_type, TestItemType::Class)) {
+            if self.conftest_fixtures.contains(&item.name) {
+                self.warnings
+                    .write()
+                    .unwrap()
+                    .push(format!("{}: test name shadows the conftest fixture '{}'", self.node_id(item), item.name));
+            }
+            for (first, second) in &self.conflicting_markers {
+                let has = |name: &String| item.effective_markers().any(|marker| marker == name);
+                if has(first) && has(second) {
+                    self.warnings
+                        .write()
+                        .unwrap()
+                        .push(format!("{}: conflicting markers '{}' and '{}'", self.node_id(item), first, second));
+                }
+            }
+        }
+
+        // CRITICAL: Apply filter HERE in Rust, not in Python!
+        // This avoids creating Python objects for filtered-out tests
+        file_meta.test_items.retain(|item| {
+            let matches = filter.matches(item);
+            self.trace(|| match matches {
+                true => format!("{}: collected", self.node_id(item)),
+                false => format!("{}: filtered out, {}", self.node_id(item), filter.rejection_reason(item)),
+            });
+            matches
+        });
+
+        // Skip file if no matching tests
+        if file_meta.test_items.is_empty() {
+            if had_items && self.report_empty_after_filter {
+                self.empty_after_filter.write().unwrap().push(file_meta.path);
+            }
+            return None;
+        }
+
+        Some(file_meta)
+    }
+
+    /// Scanned conftests in the file's directory and its parents up to the root, nearest first
+    fn applicable_conftests(&self, file_path: &Path) -> Vec<String> {
+        if self.conftests.is_empty() {
+            return Vec::new();
+        }
+        file_path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root_path))
+            .map(|dir| dir.join("conftest.py"))
+            .filter(|conftest| self.conftests.contains(conftest))
+            .map(|conftest| conftest.to_string_lossy().to_string())
+            .collect()
+    }
+
+    /// Read `.fastcollect.toml` from the root and apply the keys it defines
+    fn apply_config_file(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let config_path = self.root_path.join(".fastcollect.toml");
+        if !config_path.is_file() {
+            return Ok(());
+        }
+        let contents = fs::read_to_string(config_path)?;
+        if self.strict_config {
+            let table: toml::Table = toml::from_str(&contents)?;
+            let unknown: Vec<&str> =
+                table.keys().map(String::as_str).filter(|key| !FASTCOLLECT_CONFIG_KEYS.contains(key)).collect();
+            if !unknown.is_empty() {
+                return Err(format!("unknown keys: {}", unknown.join(", ")).into());
+            }
+        }
+        let config: FastcollectConfig = toml::from_str(&contents)?;
+
+        if let Some(patterns) = config.test_patterns {
+            self.test_patterns = patterns;
+        }
+        if let Some(patterns) = config.ignore_patterns {
+            self.ignore_patterns = patterns;
+        }
+        if let Some(prefixes) = config.function_prefixes {
+            self.function_prefixes = prefixes;
+        }
+        if let Some(prefixes) = config.class_prefixes {
+            self.class_prefixes = prefixes;
+        }
+        self.invalidate_parsed_items();
+        if let Some(cache_path) = config.cache_path {
+            // Relative cache paths are resolved against the root
+            *self.cache_path.write().unwrap() = Some(self.root_path.join(cache_path));
+            self.load_cache();
+        }
+        Ok(())
+    }
+
+    /// Fingerprint of the settings that change what parsing produces
+    /// Cached items are only valid for the options they were parsed with.
+    fn parse_options_key(&self) -> String {
+        let rootdir = self.rootdir.as_ref().map(|r| r.to_string_lossy().to_string()).unwrap_or_default();
+        format!(
+            "respect_all={};rootdir={};count_asserts={};exclude_markers_hard={};function_prefixes={};class_prefixes={};method_prefixes={:?};capture_signature={};collect_conditional_defs={};expand_parametrize={};leaf_only={};detect_syntax_features={};order_keys={};capture_offsets={};max_inheritance_depth={};unknown_parametrize_count={:?}",
+            self.respect_all,
+            rootdir,
+            self.count_asserts,
+            self.exclude_markers_hard.join(","),
+            self.function_prefixes.join(","),
+            self.class_prefixes.join(","),
+            // `Some([])` (no methods collected) must differ from `None` (function prefixes)
+            self.method_prefixes,
+            self.capture_signature,
+            self.collect_conditional_defs,
+            self.expand_parametrize,
+            self.leaf_only,
+            self.detect_syntax_features,
+            self.order_keys,
+            self.capture_offsets,
+            self.max_inheritance_depth,
+            self.unknown_parametrize_count
+        )
+    }
+
+    /// Drop in-memory cached items after a parse option changed
+    fn invalidate_parsed_items(&self) {
+        self.cache.write().unwrap().clear();
+    }
+
+    /// PHASE 3: Load cache from disk
+    fn load_cache(&self) {
+        let cache_path_opt = self.cache_path.read().unwrap().clone();
+        if let Some(cache_path) = cache_path_opt {
+            if cache_path.exists() {
+                match fs::read_to_string(&cache_path) {
+                    Ok(contents) => {
+                        match serde_json::from_str::<CacheData>(&contents) {
+                            Ok(cache_data) => {
+                                // Check version and the parse options the entries were built with
+                                if cache_data.version == CACHE_VERSION
+                                    && cache_data.options == self.parse_options_key()
+                                {
+                                    *self.cache.write().unwrap() = cache_data.entries.into_iter().collect();
+                                    *self.dir_listings.write().unwrap() = cache_data.directories.into_iter().collect();
+                                    *self.cache_file_mtime.write().unwrap() = Some(file_mtime(&cache_path));
+                                } else {
+                                    // Version mismatch, start fresh
+                                    self.cache.write().unwrap().clear();
+                                }
+                            }
+                            Err(_) => {
+                                // Corrupted cache, start fresh
+                                self.cache.write().unwrap().clear();
+                            }
+                        }
+                    }
+                    Err(_) => {
+                        // Can't read cache, start fresh
+                        self.cache.write().unwrap().clear();
+                    }
+                }
+            }
+        }
+    }
+
+    /// The cache file's contents, if it was written by this version with the same parse options
+    fn on_disk_cache(&self) -> Option<CacheData> {
+        let cache_path = self.cache_path.read().unwrap().clone()?;
+        fs::read_to_string(cache_path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<CacheData>(&contents).ok())
+            .filter(|on_disk| on_disk.version == CACHE_VERSION && on_disk.options == self.parse_options_key())
+    }
+
+    /// PHASE 3: Save cache to disk
+    fn save_cache(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let cache_path_opt = self.cache_path.read().unwrap().clone();
+        if let Some(cache_path) = cache_path_opt {
+            // Ensure parent directory exists
+            if let Some(parent) = cache_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            // Merge over what is on disk so partial or concurrent collections accumulate
+            // instead of dropping each other's entries
+            let mut cache_data = self.on_disk_cache().unwrap_or_else(|| CacheData {
+                version: CACHE_VERSION.to_string(),
+                options: self.parse_options_key(),
+                entries: BTreeMap::new(),
+                directories: BTreeMap::new(),
+            });
+            for (path, entry) in self.cache.read().unwrap().iter() {
+                let newer = cache_data.entries.get(path).is_none_or(|on_disk| entry.mtime >= on_disk.mtime);
+                if newer {
+                    cache_data.entries.insert(path.clone(), entry.clone());
+                }
+            }
+            for (path, listing) in self.dir_listings.read().unwrap().iter() {
+                let newer = cache_data.directories.get(path).is_none_or(|on_disk| listing.mtime >= on_disk.mtime);
+                if newer {
+                    cache_data.directories.insert(path.clone(), listing.clone());
+                }
+            }
+
+            // Compact like `compact_cache`, so saving never re-inflates a compacted file
+            write_atomically(&cache_path, serde_json::to_string(&cache_data)?.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Drop entries for files and directories that no longer exist and rewrite the cache file
+    /// as compact JSON; returns the number of bytes saved
+    fn compact_cache_file(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let Some(cache_path) = self.cache_path.read().unwrap().clone() else {
+            return Ok(0);
+        };
+        if !cache_path.exists() {
+            return Ok(0);
+        }
+
+        let contents = fs::read_to_string(&cache_path)?;
+        let mut cache_data: CacheData = serde_json::from_str(&contents)?;
+        cache_data.entries.retain(|path, _| Path::new(path).exists());
+        cache_data.directories.retain(|path, _| Path::new(path).exists());
+        // Keep the in-memory cache from writing the dead entries back
+        self.cache.write().unwrap().retain(|path, _| Path::new(path).exists());
+        self.dir_listings.write().unwrap().retain(|path, _| Path::new(path).exists());
+
+        // Maps are ordered, so the output is sorted
+        let json = serde_json::to_string(&cache_data)?;
+        write_atomically(&cache_path, json.as_bytes())?;
+        Ok(contents.len().saturating_sub(json.len()))
+    }
+
+    /// PHASE 3: Get cached data for a file if it's still valid
+    fn get_cached(&self, file_path: &str, current_mtime: f64) -> Option<ParsedFile> {
+        let cache = self.cache.read().unwrap();
+        if let Some(entry) = cache.get(file_path) {
+            // Files untouched since the cache was written are trusted as-is
+            if self.skip_older_than_cache {
+                if let Some(cache_file_mtime) = *self.cache_file_mtime.read().unwrap() {
+                    if current_mtime <= cache_file_mtime {
+                        return Some(entry.parsed.clone());
+                    }
+                }
+            }
+
+            let mtime_matches = (entry.mtime - current_mtime).abs() < MTIME_TOLERANCE_SECONDS;
+            let Some(algorithm) = self.hash_algorithm else {
+                // Check if mtime matches (within tolerance)
+                return mtime_matches.then(|| entry.parsed.clone());
+            };
+
+            // Entries hashed with another algorithm (or none) are stale
+            let stored = entry.content_hash.as_ref()?;
+            if !stored.starts_with(algorithm.name()) {
+                return None;
+            }
+            if mtime_matches {
+                return Some(entry.parsed.clone());
+            }
+            if self.content_hash(Path::new(file_path)).as_ref() == Some(stored) {
+                let parsed = entry.parsed.clone();
+                drop(cache);
+                // Same content under a new mtime: record it so later runs match without hashing
+                if let Some(entry) = self.cache.write().unwrap().get_mut(file_path) {
+                    entry.mtime = current_mtime;
+                }
+                return Some(parsed);
+            }
+        }
+        None
+    }
+
+    /// Digest of a file's content with the configured algorithm, if any
+    fn content_hash(&self, path: &Path) -> Option<String> {
+        let algorithm = self.hash_algorithm?;
+        fs::read(path).ok().map(|content| algorithm.digest(&content))
+    }
+
+    /// PHASE 3: Update cache with newly parsed data
+    fn update_cache(&self, file_path: String, entry: CacheEntry) {
+        self.cache.write().unwrap().insert(file_path, entry);
+    }
+
+    /// Find all test files in the directory tree
+    /// Each file is returned once, even if reachable under several spellings of its path
+    fn find_test_files(&self) -> Vec<PathBuf> {
+        let test_files = if self.incremental_walk {
+            self.find_test_files_incremental()
+        } else {
+            self.walk_test_files()
+        };
+        dedupe_paths(test_files)
+    }
+
+    fn walk_test_files(&self) -> Vec<PathBuf> {
+        self.walk_roots()
+            .into_iter()
+            .flat_map(|root| {
+                WalkDir::new(root)
+                    .into_iter()
+                    .filter_entry(|e| {
+                        // Skip ignored directories
+                        !self.should_ignore(e.path())
+                    })
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_file())
+                    .filter(|e| self.is_test_file(e.path()))
+                    .map(|e| e.path().to_path_buf())
+            })
+            .collect()
+    }
+
+    /// Directories the walk starts from: the added roots, or `root_path` when none were added
+    fn walk_roots(&self) -> Vec<PathBuf> {
+        if self.roots.is_empty() {
+            vec![self.root_path.clone()]
+        } else {
+            self.roots.iter().map(|(root, _)| root.clone()).collect()
+        }
+    }
+
+    /// Walk the tree reusing the listings of directories whose mtime is unchanged
+    /// Files come back sorted by path rather than in the directory order of a full walk.
+    fn find_test_files_incremental(&self) -> Vec<PathBuf> {
+        let mut test_files = Vec::new();
+        let mut pending = self.walk_roots();
+
+        while let Some(dir) = pending.pop() {
+            if self.should_ignore(&dir) {
+                continue;
+            }
+            let Some(listing) = self.dir_listing(&dir) else {
+                continue;
+            };
+            test_files.extend(
+                listing
+                    .files
+                    .iter()
+                    .map(|name| dir.join(name))
+                    .filter(|path| !self.should_ignore(path) && self.is_test_file(path)),
+            );
+            pending.extend(listing.dirs.iter().map(|name| dir.join(name)));
+        }
+
+        test_files.sort();
+        test_files
+    }
+
+    /// Listing of a directory, from the stored listings when its mtime is unchanged
+    fn dir_listing(&self, dir: &Path) -> Option<DirListing> {
+        let key = dir.to_string_lossy().to_string();
+        let mtime = file_mtime(dir);
+        if let Some(listing) = self.dir_listings.read().unwrap().get(&key) {
+            // Exact comparison: an entry added right after listing must not be missed
+            if listing.mtime == mtime {
+                return Some(listing.clone());
+            }
+        }
+
+        self.stats.dirs_listed.fetch_add(1, Ordering::Relaxed);
+        let mut listing = DirListing {
+            mtime,
+            files: Vec::new(),
+            dirs: Vec::new(),
+        };
+        for entry in fs::read_dir(dir).ok()?.filter_map(|e| e.ok()) {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().to_string();
+            if file_type.is_dir() {
+                listing.dirs.push(name);
+            } else if file_type.is_file() {
+                listing.files.push(name);
+            }
+        }
+        listing.files.sort();
+        listing.dirs.sort();
+
+        self.dir_listings.write().unwrap().insert(key, listing.clone());
+        Some(listing)
+    }
+
+    /// Check if a path should be ignored
+    /// Patterns of an added root only apply to paths descending from it.
+    fn should_ignore(&self, path: &Path) -> bool {
+        if self.collect_ignore.contains(path) {
+            return true;
+        }
+        if let Some(name) = path.file_name() {
+            let name_str = name.to_string_lossy();
+            let root_patterns = self
+                .roots
+                .iter()
+                .filter(|(root, _)| path.starts_with(root))
+                .flat_map(|(_, ignores)| ignores);
+            for pattern in self.ignore_patterns.iter().chain(root_patterns) {
+                if pattern.contains('*') {
+                    // Simple wildcard matching
+                    if self.matches_wildcard(&name_str, pattern) {
+                        return true;
+                    }
+                } else if name_str == pattern.as_str() {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Simple wildcard matching (supports * anywhere in pattern)
+    fn matches_wildcard(&self, text: &str, pattern: &str) -> bool {
+        matches_wildcard(text, pattern)
+    }
+
+    /// Check if a file is a test file based on naming patterns
+    fn is_test_file(&self, path: &Path) -> bool {
+        if let Some(name) = path.file_name() {
+            let name_str = name.to_string_lossy();
+            if !name_str.ends_with(".py") {
+                return false;
+            }
+            for pattern in &self.test_patterns {
+                if self.matches_wildcard(&name_str, pattern) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Parse a test file and extract test items
+    #[cfg(test)]
+    fn parse_test_file(&self, path: &Path) -> Result<Vec<TestItem>, Box<dyn std::error::Error>> {
+        Ok(self.parse_file(path)?.items)
+    }
+
+    /// Parse a test file and extract test items along with file-level facts
+    fn parse_file(&self, path: &Path) -> Result<ParsedFile, Box<dyn std::error::Error>> {
+        self.parse_bytes(path, &fs::read(path)?)
+    }
+
+    /// Like `parse_file` for content already read from `path`
+    fn parse_bytes(&self, path: &Path, bytes: &[u8]) -> Result<ParsedFile, Box<dyn std::error::Error>> {
+        let Some(content) = decode_source(bytes) else {
+            self.warnings.write().unwrap().push(format!("Skipping undecodable test file {}", path.display()));
+            return Err(format!("{}: not UTF-8, UTF-16 with a BOM or Latin-1 with a coding cookie", path.display()).into());
+        };
+        Ok(self.parse_source(&content, &self.report_path(path)))
+    }
+
+    /// Path recorded on items: relative to the rootdir when one is set, as given otherwise
+    fn report_path(&self, path: &Path) -> String {
+        match self.rootdir {
+            Some(ref rootdir) => path.strip_prefix(rootdir).unwrap_or(path).to_string_lossy().to_string(),
+            None => path.to_string_lossy().to_string(),
+        }
+    }
+
+    /// Extract test items and file-level facts from source text
+    fn parse_source(&self, content: &str, file_path: &str) -> ParsedFile {
+        let module = match ast::Suite::parse(content, file_path) {
+            Ok(m) => m,
+            Err(error) => {
+                // Skip files with parse errors, keeping the location for reporting
+                let location = LineIndex::from_source_text(content).source_location(error.offset, content);
+                return ParsedFile {
+                    parse_error: Some(FileParseError {
+                        file_path: file_path.to_string(),
+                        message: error.error.to_string(),
+                        line: location.row.to_usize(),
+                        column: location.column.to_usize(),
+                    }),
+                    ..ParsedFile::default()
+                };
+            }
+        };
+
+        let module_scope = Scope {
+            markers: self.extract_pytestmark(&module),
+            skip_reason: self.extract_pytestmark_skip_reason(&module),
+            ..Scope::default()
+        };
+        let exported = if self.respect_all {
+            self.extract_dunder_all(&module)
+        } else {
+            None
+        };
+        let mut source = SourceFile::new(file_path, content);
+        source.marker_factories = self.extract_marker_factories(&module);
+        source.mark_aliases = self.extract_mark_aliases(&module);
+        source.classes = module
+            .iter()
+            .filter_map(|stmt| match stmt {
+                ast::Stmt::ClassDef(class) => Some((class.name.to_string(), class)),
+                _ => None,
+            })
+            .collect();
+        let mut items = Vec::new();
+
+        for stmt in &module {
+            if let Some(ref exported) = exported {
+                let name = match stmt {
+                    ast::Stmt::FunctionDef(func) => Some(func.name.as_str()),
+                    ast::Stmt::AsyncFunctionDef(func) => Some(func.name.as_str()),
+                    ast::Stmt::ClassDef(class) => Some(class.name.as_str()),
+                    _ => None,
+                };
+                if let Some(name) = name {
+                    if !exported.contains(&normalize_identifier(name)) {
+                        continue;
+                    }
+                }
+            }
+            self.extract_test_items(stmt, &source, &module_scope, &mut items);
+        }
+        if !self.exclude_markers_hard.is_empty() {
+            items.retain(|item| !item.effective_markers().any(|marker| self.exclude_markers_hard.contains(marker)));
+        }
+        if self.leaf_only {
+            items.retain(|item| !matches!(item.item_type, TestItemType::Class));
+        }
+        if self.expand_parametrize || self.leaf_only {
+            items = items.into_iter().flat_map(TestItem::expanded).collect();
+        }
+        if self.order_keys {
+            let relative = self.relative_path(Path::new(file_path));
+            for item in &mut items {
+                let case = item.parametrize_id.as_ref().map(|id| format!("[{}]", id)).unwrap_or_default();
+                item.order_key = Some(format!("{}::{}{}", relative, item.qualname, case));
+            }
+        }
+
+        let conditional_skip_module = self.extract_importorskip(&module);
+        let imports = self.extract_imports(&module);
+        let warnings = source.warnings.take();
+        // Last, as folding consumes the module
+        let syntax_features = if self.detect_syntax_features { self.syntax_features(module) } else { Vec::new() };
+
+        ParsedFile {
+            items,
+            conditional_skip_module,
+            parse_error: None,
+            syntax_features,
+            module_markers: module_scope.markers,
+            imports,
+            warnings,
+        }
+    }
+
+    /// Version-gated syntax features used anywhere in the module, sorted
+    fn syntax_features(&self, module: ast::Suite) -> Vec<String> {
+        let mut features = SyntaxFeatures::default();
+        for stmt in module {
+            // Folding is infallible
+            let _ = ast::Fold::fold_stmt(&mut features, stmt);
+        }
+        features.found.into_iter().map(str::to_string).collect()
+    }
+
+    /// Marker names from `<x>.addinivalue_line("markers", "name: description")` calls,
+    /// searched through function bodies such as `pytest_configure`
+    fn extract_marker_registrations(&self, body: &[ast::Stmt], markers: &mut Vec<String>) {
+        for stmt in body {
+            match stmt {
+                ast::Stmt::FunctionDef(func) => self.extract_marker_registrations(&func.body, markers),
+                ast::Stmt::Expr(expr_stmt) => {
+                    let ast::Expr::Call(call) = expr_stmt.value.as_ref() else {
+                        continue;
+                    };
+                    let is_addinivalue = matches!(call.func.as_ref(), ast::Expr::Attribute(attr) if attr.attr.as_str() == "addinivalue_line");
+                    let (Some(ast::Expr::Constant(option)), Some(ast::Expr::Constant(line))) = (call.args.first(), call.args.get(1)) else {
+                        continue;
+                    };
+                    if !is_addinivalue || option.value.as_str().map(|s| s.as_str()) != Some("markers") {
+                        continue;
+                    }
+                    if let Some(line) = line.value.as_str() {
+                        // "slow: description" or "env(name): description"
+                        let name = line.split([':', '(']).next().unwrap_or("").trim();
+                        if !name.is_empty() {
+                            markers.push(name.to_string());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Names of module-level functions decorated with `@pytest.fixture` / `@fixture`,
+    /// honoring `@pytest.fixture(name="...")`
+    fn extract_fixture_names(&self, module: &[ast::Stmt]) -> Vec<String> {
+        module.iter().filter_map(FunctionDef::from_stmt).filter_map(|func| self.fixture_name(&func)).collect()
+    }
+
+    /// Name a function is registered under as a fixture, `None` when it is not one
+    fn fixture_name(&self, func: &FunctionDef) -> Option<String> {
+        func.decorator_list.iter().find_map(|decorator| {
+            let (target, keywords) = match decorator {
+                ast::Expr::Call(call) => (call.func.as_ref(), call.keywords.as_slice()),
+                other => (other, &[][..]),
+            };
+            let is_fixture = match target {
+                ast::Expr::Name(name) => name.id.as_str() == "fixture",
+                ast::Expr::Attribute(attr) => {
+                    attr.attr.as_str() == "fixture"
+                        && matches!(attr.value.as_ref(), ast::Expr::Name(name) if name.id.as_str() == "pytest")
+                }
+                _ => false,
+            };
+            if !is_fixture {
+                return None;
+            }
+            let renamed = keywords.iter().find_map(|kw| match (&kw.arg, &kw.value) {
+                (Some(arg), ast::Expr::Constant(c)) if arg.as_str() == "name" => c.value.as_str().cloned(),
+                _ => None,
+            });
+            Some(renamed.unwrap_or_else(|| func.name.to_string()))
+        })
+    }
+
+    /// Strings of a literal module-level `collect_ignore = [...]` list or tuple
+    fn extract_collect_ignore(&self, module: &[ast::Stmt]) -> Vec<String> {
+        module
+            .iter()
+            .filter_map(|stmt| {
+                let ast::Stmt::Assign(assign) = stmt else {
+                    return None;
+                };
+                let is_collect_ignore = assign
+                    .targets
+                    .iter()
+                    .any(|target| matches!(target, ast::Expr::Name(name) if name.id.as_str() == "collect_ignore"));
+                let elts = match assign.value.as_ref() {
+                    ast::Expr::List(list_expr) => &list_expr.elts,
+                    ast::Expr::Tuple(tuple_expr) => &tuple_expr.elts,
+                    _ => return None,
+                };
+                is_collect_ignore.then_some(elts)
+            })
+            .flatten()
+            .filter_map(|elt| match elt {
+                ast::Expr::Constant(c) => c.value.as_str().cloned(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Absolute module-level imports, sorted and deduplicated
+    /// `from a import b` records both `a` and `a.b` since `b` may be a submodule.
+    fn extract_imports(&self, module: &[ast::Stmt]) -> Vec<String> {
+        let mut imports = BTreeSet::new();
+        for stmt in module {
+            match stmt {
+                ast::Stmt::Import(import) => {
+                    imports.extend(import.names.iter().map(|alias| alias.name.to_string()));
+                }
+                ast::Stmt::ImportFrom(import) if import.level.map_or(0, |level| level.to_u32()) == 0 => {
+                    let Some(base) = &import.module else {
+                        continue;
+                    };
+                    imports.insert(base.to_string());
+                    for alias in import.names.iter().filter(|alias| alias.name.as_str() != "*") {
+                        imports.insert(format!("{}.{}", base, alias.name));
+                    }
+                }
+                _ => {}
+            }
+        }
+        imports.into_iter().collect()
+    }
+
+    /// Module name from a top-level `pytest.importorskip("numpy")` call
+    /// (bare statement or assignment such as `np = pytest.importorskip("numpy")`)
+    fn extract_importorskip(&self, module: &[ast::Stmt]) -> Option<String> {
+        module.iter().find_map(|stmt| {
+            let value = match stmt {
+                ast::Stmt::Expr(expr_stmt) => expr_stmt.value.as_ref(),
+                ast::Stmt::Assign(assign) => assign.value.as_ref(),
+                _ => return None,
+            };
+            let ast::Expr::Call(call) = value else {
+                return None;
+            };
+            let ast::Expr::Attribute(attr) = call.func.as_ref() else {
+                return None;
+            };
+            let is_importorskip = attr.attr.as_str() == "importorskip"
+                && matches!(attr.value.as_ref(), ast::Expr::Name(name) if name.id.as_str() == "pytest");
+            if !is_importorskip {
+                return None;
+            }
+
+            let modname = call.args.first().or_else(|| {
+                call.keywords
+                    .iter()
+                    .find(|kw| kw.arg.as_ref().is_some_and(|arg| arg.as_str() == "modname"))
+                    .map(|kw| &kw.value)
+            });
+            match modname {
+                Some(ast::Expr::Constant(c)) => c.value.as_str().cloned(),
+                _ => None,
+            }
+        })
+    }
+
+    /// Extract the names listed in a literal module-level `__all__` list or tuple
+    fn extract_dunder_all(&self, module: &[ast::Stmt]) -> Option<HashSet<String>> {
+        let mut exported = None;
+
+        for stmt in module {
+            if let ast::Stmt::Assign(assign) = stmt {
+                let is_dunder_all = assign
+                    .targets
+                    .iter()
+                    .any(|target| matches!(target, ast::Expr::Name(name) if name.id.as_str() == "__all__"));
+                if !is_dunder_all {
+                    continue;
+                }
+
+                let elts = match assign.value.as_ref() {
+                    ast::Expr::List(list_expr) => &list_expr.elts,
+                    ast::Expr::Tuple(tuple_expr) => &tuple_expr.elts,
+                    _ => continue,
+                };
+                let names = elts
+                    .iter()
+                    .filter_map(|elt| match elt {
+                        ast::Expr::Constant(c) => c.value.as_str().map(|s| normalize_identifier(s)),
+                        _ => None,
+                    })
+                    .collect();
+                exported = Some(names);
+            }
+        }
+
+        exported
+    }
+
+    /// Extract markers from a `pytestmark = ...` assignment in a module or class body
+    /// Accepts a single mark or a list/tuple of marks
+    fn extract_pytestmark(&self, body: &[ast::Stmt]) -> Vec<String> {
+        self.pytestmark_exprs(body)
+            .into_iter()
+            .flat_map(|expr| self.extract_markers(std::slice::from_ref(expr)))
+            .collect()
+    }
+
+    /// Skip reason from a `pytestmark` assignment in a module or class body
+    fn extract_pytestmark_skip_reason(&self, body: &[ast::Stmt]) -> Option<String> {
+        self.pytestmark_exprs(body)
+            .into_iter()
+            .find_map(|expr| self.extract_skip_reason(std::slice::from_ref(expr)))
+    }
+
+    /// Marker expressions assigned to `pytestmark` (single value, list or tuple)
+    fn pytestmark_exprs<'a>(&self, body: &'a [ast::Stmt]) -> Vec<&'a ast::Expr> {
+        let mut exprs = Vec::new();
+
+        for stmt in body {
+            let (targets, value): (Vec<&ast::Expr>, &ast::Expr) = match stmt {
+                ast::Stmt::Assign(assign) => (assign.targets.iter().collect(), assign.value.as_ref()),
+                ast::Stmt::AnnAssign(assign) => match assign.value {
+                    Some(ref value) => (vec![assign.target.as_ref()], value.as_ref()),
+                    None => continue,
+                },
+                _ => continue,
+            };
+
+            let is_pytestmark = targets
+                .iter()
+                .any(|target| matches!(target, ast::Expr::Name(name) if name.id.as_str() == "pytestmark"));
+            if !is_pytestmark {
+                continue;
+            }
+
+            match value {
+                ast::Expr::List(list_expr) => exprs.extend(list_expr.elts.iter()),
+                ast::Expr::Tuple(tuple_expr) => exprs.extend(tuple_expr.elts.iter()),
+                other => exprs.push(other),
+            }
+        }
+
+        exprs
+    }
+
+    /// Extract test items from AST nodes
+    fn extract_test_items(
+        &self,
+        stmt: &ast::Stmt,
+        source: &SourceFile,
+        scope: &Scope,
+        items: &mut Vec<TestItem>,
+    ) {
+        match stmt {
+            ast::Stmt::FunctionDef(_) | ast::Stmt::AsyncFunctionDef(_) => {
+                let Some(func) = FunctionDef::from_stmt(stmt) else {
+                    return;
+                };
+                let name = normalize_identifier(func.name);
+                let is_test = if scope.class_name.is_some() {
+                    self.is_test_method(&name)
+                } else {
+                    self.is_test_function(&name)
+                };
+                // Fixtures named like tests are not tests; fixture-only modules end up empty
+                if is_test && self.fixture_name(&func).is_none() {
+                    let markers = self.extract_decorator_markers(func.decorator_list, source);
+                    // A class-level parametrize multiplies the method's own cases
+                    let parametrize_count = match (self.extract_parametrize_count(func.decorator_list, source), scope.parametrize_count) {
+                        (Some(own), Some(class)) => Some(own * class),
+                        (own, class) => own.or(class),
+                    };
+
+                    let mut requested_fixtures = self.extract_argument_fixtures(&func, scope.class_name.is_some());
+                    requested_fixtures.retain(|fixture| !scope.parametrize_argnames.contains(fixture));
+                    for fixture in scope.usefixtures.iter().cloned().chain(self.extract_usefixtures(func.decorator_list)) {
+                        if !requested_fixtures.contains(&fixture) {
+                            requested_fixtures.push(fixture);
+                        }
+                    }
+
+                    items.push(TestItem {
+                        file_path: source.path.to_string(),
+                        qualname: qualname(scope.class_name.as_deref(), &name),
+                        name,
+                        line_number: source.line(func.range.start()),
+                        end_line: source.line(func.range.end()),
+                        byte_start: self.capture_offsets.then(|| func.range.start().to_usize()),
+                        byte_end: self.capture_offsets.then(|| func.range.end().to_usize()),
+                        item_type: if scope.class_name.is_some() {
+                            TestItemType::Method
+                        } else {
+                            TestItemType::Function
+                        },
+                        is_async: func.is_async,
+                        class_name: scope.class_name.clone(),
+                        markers,
+                        inherited_markers: scope.markers.clone(),
+                        parametrize_count,
+                        parametrize_indirect: self.extract_parametrize_indirect(func.decorator_list),
+                        // Case ids would need the class-level values too
+                        parametrize_cases: if scope.parametrize_count.is_none() {
+                            self.extract_parametrize_cases(func.decorator_list)
+                        } else {
+                            Vec::new()
+                        },
+                        requested_fixtures,
+                        skip_reason: self
+                            .extract_skip_reason(func.decorator_list)
+                            .or_else(|| scope.skip_reason.clone()),
+                        timeout: self.extract_timeout(func.decorator_list),
+                        parametrize_id: None,
+                        order_key: None,
+                        signature: if self.capture_signature { self.function_signature(&func) } else { String::new() },
+                        assert_count: if self.count_asserts { self.count_assert_stmts(func.body) } else { 0 },
+                    });
+                }
+            }
+            ast::Stmt::ClassDef(class) => {
+                let class_name = normalize_identifier(class.name.as_str());
+                if self.is_test_class(&class_name) {
+                    let markers = self.extract_decorator_markers(&class.decorator_list, source);
+
+                    // Methods inherit module markers, class decorators and the class `pytestmark`
+                    let mut class_inherited = scope.markers.clone();
+                    class_inherited.extend(self.extract_pytestmark(&class.body));
+                    // Nested test classes compose their path: `TestAPI::TestGet`
+                    let class_path = match scope.class_name {
+                        Some(ref outer) => format!("{}::{}", outer, class_name),
+                        None => class_name.clone(),
+                    };
+                    let parametrize_count = match (self.extract_parametrize_count(&class.decorator_list, source), scope.parametrize_count) {
+                        (Some(own), Some(outer)) => Some(own * outer),
+                        (own, outer) => own.or(outer),
+                    };
+                    let mut method_scope = Scope {
+                        class_name: Some(class_path),
+                        markers: class_inherited.clone(),
+                        usefixtures: scope.usefixtures.clone(),
+                        parametrize_count,
+                        parametrize_argnames: scope.parametrize_argnames.clone(),
+                        // The closest reason wins: class decorators, class `pytestmark`, then outer scopes
+                        skip_reason: self
+                            .extract_skip_reason(&class.decorator_list)
+                            .or_else(|| self.extract_pytestmark_skip_reason(&class.body))
+                            .or_else(|| scope.skip_reason.clone()),
+                    };
+                    method_scope.parametrize_argnames.extend(self.extract_parametrize_argnames(&class.decorator_list));
+                    method_scope.markers.extend(markers.iter().cloned());
+                    method_scope.usefixtures.extend(self.extract_usefixtures(&class.decorator_list));
+
+                    // Add the class itself
+                    items.push(TestItem {
+                        file_path: source.path.to_string(),
+                        qualname: qualname(scope.class_name.as_deref(), &class_name),
+                        name: class_name,
+                        line_number: source.line(class.range.start()),
+                        end_line: source.line(class.range.end()),
+                        byte_start: self.capture_offsets.then(|| class.range.start().to_usize()),
+                        byte_end: self.capture_offsets.then(|| class.range.end().to_usize()),
+                        item_type: TestItemType::Class,
+                        is_async: false,
+                        class_name: scope.class_name.clone(),
+                        markers,
+                        inherited_markers: class_inherited,
+                        parametrize_count: None,
+                        parametrize_indirect: false,
+                        parametrize_cases: Vec::new(),
+                        requested_fixtures: Vec::new(),
+                        skip_reason: method_scope.skip_reason.clone(),
+                        timeout: None,
+                        parametrize_id: None,
+                        order_key: None,
+                        signature: String::new(),
+                        assert_count: 0,
+                    });
+
+                    // Extract methods and nested test classes
+                    for stmt in &class.body {
+                        self.extract_test_items(stmt, source, &method_scope, items);
+                    }
+                    self.extract_inherited_methods(class, source, &method_scope, items);
+                }
+            }
+            ast::Stmt::If(if_stmt) if self.collect_conditional_defs && scope.class_name.is_none() => {
+                let body = if is_main_guard(&if_stmt.test) { &[][..] } else { &if_stmt.body[..] };
+                // `elif` chains are nested in `orelse`
+                for stmt in body.iter().chain(&if_stmt.orelse) {
+                    self.extract_test_items(stmt, source, scope, items);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Test methods a class inherits from base classes defined in the same module
+    /// Bases are searched depth-first, left to right; a method defined by the class itself or by
+    /// an earlier base hides later ones with the same name. Bases beyond the maximum depth and
+    /// cyclic definitions are skipped with a warning.
+    fn extract_inherited_methods(
+        &self,
+        class: &ast::StmtClassDef,
+        source: &SourceFile,
+        method_scope: &Scope,
+        items: &mut Vec<TestItem>,
+    ) {
+        let method_names = |class: &ast::StmtClassDef| -> Vec<String> {
+            class
+                .body
+                .iter()
+                .filter_map(FunctionDef::from_stmt)
+                .map(|func| func.name.to_string())
+                .collect()
+        };
+        let mut seen: HashSet<String> = method_names(class).into_iter().collect();
+        let mut visited = HashSet::from([class.name.as_str()]);
+        // Each pending base with the chain of classes leading to it
+        let root_chain = vec![class.name.as_str()];
+        let mut pending: Vec<(&ast::Expr, Vec<&str>)> =
+            class.bases.iter().rev().map(|base| (base, root_chain.clone())).collect();
+
+        while let Some((base, chain)) = pending.pop() {
+            let ast::Expr::Name(base_name) = base else {
+                continue;
+            };
+            let Some(base_class) = source.classes.get(base_name.id.as_str()) else {
+                continue;
+            };
+            if chain.contains(&base_name.id.as_str()) {
+                source.warnings.borrow_mut().push(format!(
+                    "{}: cyclic inheritance of {} through {}",
+                    source.path, class.name, base_name.id
+                ));
+                continue;
+            }
+            if chain.len() > self.max_inheritance_depth {
+                source.warnings.borrow_mut().push(format!(
+                    "{}: bases of {} nested deeper than {} levels are ignored",
+                    source.path, class.name, self.max_inheritance_depth
+                ));
+                continue;
+            }
+            // Shared bases (diamonds) contribute once
+            if !visited.insert(base_name.id.as_str()) {
+                continue;
+            }
+
+            for stmt in &base_class.body {
+                let Some(func) = FunctionDef::from_stmt(stmt) else {
+                    continue;
+                };
+                if seen.insert(func.name.to_string()) {
+                    self.extract_test_items(stmt, source, method_scope, items);
+                }
+            }
+            let mut base_chain = chain.clone();
+            base_chain.push(base_name.id.as_str());
+            pending.extend(base_class.bases.iter().rev().map(|base| (base, base_chain.clone())));
+        }
+    }
+
+    /// Name of the marker for `pytest.mark.<name>` or `mark.<name>` expressions
+    fn mark_name<'a>(&self, expr: &'a ast::Expr) -> Option<&'a str> {
+        let ast::Expr::Attribute(attr) = expr else {
+            return None;
+        };
+
+        let is_mark_namespace = match attr.value.as_ref() {
+            ast::Expr::Attribute(parent_attr) => {
+                matches!(parent_attr.value.as_ref(), ast::Expr::Name(name) if name.id.as_str() == "pytest")
+                    && parent_attr.attr.as_str() == "mark"
+            }
+            ast::Expr::Name(name) => name.id.as_str() == "mark",
+            _ => false,
+        };
+
+        is_mark_namespace.then(|| attr.attr.as_str())
+    }
+
+    /// Extract pytest markers from decorator list
+    fn extract_markers(&self, decorators: &[ast::Expr]) -> Vec<String> {
+        decorators
+            .iter()
+            .filter_map(|decorator| match decorator {
+                // Handle @pytest.mark.marker_name(...) or @mark.marker_name(...)
+                ast::Expr::Call(call) => self.mark_name(&call.func),
+                // Handle @pytest.mark.marker_name or @mark.marker_name
+                other => self.mark_name(other),
+            })
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    /// Markers of a decorator list, resolving calls of the file's marker factories (`@mark_slow()`)
+    /// and aliases of the mark namespace (`@m.slow`)
+    fn extract_decorator_markers(&self, decorators: &[ast::Expr], source: &SourceFile) -> Vec<String> {
+        decorators
+            .iter()
+            .filter_map(|decorator| match decorator {
+                ast::Expr::Call(call) => match call.func.as_ref() {
+                    ast::Expr::Name(name) => source.marker_factories.get(name.id.as_str()).cloned(),
+                    func => self.decorator_mark_name(func, source).map(str::to_string),
+                },
+                other => self.decorator_mark_name(other, source).map(str::to_string),
+            })
+            .collect()
+    }
+
+    /// Like `mark_name`, also accepting the file's aliases of the mark namespace (`m.slow`)
+    fn decorator_mark_name<'a>(&self, expr: &'a ast::Expr, source: &SourceFile) -> Option<&'a str> {
+        self.mark_name(expr).or_else(|| match expr {
+            ast::Expr::Attribute(attr) => match attr.value.as_ref() {
+                ast::Expr::Name(name) if source.mark_aliases.contains(name.id.as_str()) => Some(attr.attr.as_str()),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    /// Names assigned the mark namespace at module level (`m = pytest.mark`)
+    fn extract_mark_aliases(&self, module: &[ast::Stmt]) -> HashSet<String> {
+        module
+            .iter()
+            .filter_map(|stmt| {
+                let ast::Stmt::Assign(assign) = stmt else {
+                    return None;
+                };
+                let [ast::Expr::Name(target)] = assign.targets.as_slice() else {
+                    return None;
+                };
+                let ast::Expr::Attribute(attr) = assign.value.as_ref() else {
+                    return None;
+                };
+                let is_mark = attr.attr.as_str() == "mark"
+                    && matches!(attr.value.as_ref(), ast::Expr::Name(name) if name.id.as_str() == "pytest");
+                is_mark.then(|| target.id.to_string())
+            })
+            .collect()
+    }
+
+    /// Module-level functions whose body is a single `return pytest.mark.<name>` (optionally called)
+    /// Anything more dynamic cannot be resolved statically and is ignored.
+    fn extract_marker_factories(&self, module: &[ast::Stmt]) -> HashMap<String, String> {
+        module
+            .iter()
+            .filter_map(|stmt| {
+                let ast::Stmt::FunctionDef(func) = stmt else {
+                    return None;
+                };
+                let [ast::Stmt::Return(ret)] = func.body.as_slice() else {
+                    return None;
+                };
+                let marker = self.extract_markers(std::slice::from_ref(ret.value.as_deref()?)).pop()?;
+                Some((func.name.to_string(), marker))
+            })
+            .collect()
+    }
+
+    /// Calls of a given marker in a decorator list, e.g. every `@pytest.mark.usefixtures(...)`
+    /// Gives access to the marker arguments
+    fn marker_calls<'a>(&self, decorators: &'a [ast::Expr], marker: &str) -> Vec<&'a ast::ExprCall> {
+        decorators
+            .iter()
+            .filter_map(|decorator| match decorator {
+                ast::Expr::Call(call) if self.mark_name(&call.func) == Some(marker) => Some(call),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Fixture names listed in `@pytest.mark.usefixtures("a", "b")`
+    fn extract_usefixtures(&self, decorators: &[ast::Expr]) -> Vec<String> {
+        self.marker_calls(decorators, "usefixtures")
+            .into_iter()
+            .flat_map(|call| call.args.iter())
+            .filter_map(|arg| match arg {
+                ast::Expr::Constant(c) => c.value.as_str().cloned(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Fixtures requested as function arguments, like pytest's `getfuncargnames()`
+    /// Skips `self`/`cls` on methods, arguments with defaults and direct parametrize arguments
+    fn extract_argument_fixtures(&self, func: &FunctionDef, is_method: bool) -> Vec<String> {
+        let parametrized = self.extract_parametrize_argnames(func.decorator_list);
+
+        func.args
+            .posonlyargs
+            .iter()
+            .chain(func.args.args.iter())
+            .skip(usize::from(is_method))
+            .chain(func.args.kwonlyargs.iter())
+            .filter(|arg| arg.default.is_none())
+            .map(|arg| normalize_identifier(arg.def.arg.as_str()))
+            .filter(|name| !parametrized.contains(name))
+            .collect()
+    }
+
+    /// Literal reason of the first `skip`/`skipif` marker: `reason="..."`, or the
+    /// positional reason of `skip("...")`
+    fn extract_skip_reason(&self, decorators: &[ast::Expr]) -> Option<String> {
+        let literal = |expr: &ast::Expr| match expr {
+            ast::Expr::Constant(c) => c.value.as_str().cloned(),
+            _ => None,
+        };
+        ["skip", "skipif"].iter().find_map(|marker| {
+            self.marker_calls(decorators, marker).into_iter().find_map(|call| {
+                let keyword = call
+                    .keywords
+                    .iter()
+                    .find(|keyword| keyword.arg.as_ref().map(|arg| arg.as_str()) == Some("reason"));
+                match keyword {
+                    Some(keyword) => literal(&keyword.value),
+                    None if *marker == "skip" => call.args.first().and_then(literal),
+                    None => None,
+                }
+            })
+        })
+    }
+
+    /// First argument of `@pytest.mark.timeout(...)` (positional or `timeout=`) as seconds
+    fn extract_timeout(&self, decorators: &[ast::Expr]) -> Option<f64> {
+        let call = self.marker_calls(decorators, "timeout").into_iter().next()?;
+        let arg = call.args.first().or_else(|| {
+            call.keywords
+                .iter()
+                .find(|keyword| keyword.arg.as_ref().map(|arg| arg.as_str()) == Some("timeout"))
+                .map(|keyword| &keyword.value)
+        })?;
+        match arg {
+            ast::Expr::Constant(c) => match &c.value {
+                ast::Constant::Int(i) => i.to_string().parse().ok(),
+                ast::Constant::Float(f) => Some(*f),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// `name(a, b, /, c, *args, d, **kwargs)` with default values omitted
+    fn function_signature(&self, func: &FunctionDef) -> String {
+        let args = func.args;
+        let mut params: Vec<String> = args.posonlyargs.iter().map(|arg| arg.def.arg.to_string()).collect();
+        if !params.is_empty() {
+            params.push("/".to_string());
+        }
+        params.extend(args.args.iter().map(|arg| arg.def.arg.to_string()));
+        match args.vararg {
+            Some(ref vararg) => params.push(format!("*{}", vararg.arg)),
+            None if !args.kwonlyargs.is_empty() => params.push("*".to_string()),
+            None => {}
+        }
+        params.extend(args.kwonlyargs.iter().map(|arg| arg.def.arg.to_string()));
+        if let Some(ref kwarg) = args.kwarg {
+            params.push(format!("**{}", kwarg.arg));
+        }
+        format!("{}({})", normalize_identifier(func.name), params.join(", "))
+    }
+
+    /// Argument names parametrized by `@pytest.mark.parametrize("a,b", ...)` or `(["a", "b"], ...)`
+    fn extract_parametrize_argnames(&self, decorators: &[ast::Expr]) -> Vec<String> {
+        let mut names = Vec::new();
+
+        for call in self.marker_calls(decorators, "parametrize") {
+            match call.args.first() {
+                Some(ast::Expr::Constant(c)) => {
+                    if let Some(argnames) = c.value.as_str() {
+                        names.extend(argnames.split(',').map(|n| n.trim().to_string()).filter(|n| !n.is_empty()));
+                    }
+                }
+                Some(ast::Expr::List(ast::ExprList { elts, .. })) | Some(ast::Expr::Tuple(ast::ExprTuple { elts, .. })) => {
+                    names.extend(elts.iter().filter_map(|elt| match elt {
+                        ast::Expr::Constant(c) => c.value.as_str().cloned(),
+                        _ => None,
+                    }));
+                }
+                _ => {}
+            }
+        }
+
+        names
+    }
+
+    /// Cases of a single `@pytest.mark.parametrize` with literal argvalues, with pytest-style ids
+    /// and `pytest.param(..., marks=..., id=...)` details. Empty for stacked or dynamic parametrize.
+    fn extract_parametrize_cases(&self, decorators: &[ast::Expr]) -> Vec<ParamCase> {
+        let calls = self.marker_calls(decorators, "parametrize");
+        let [call] = calls.as_slice() else {
+            return Vec::new();
+        };
+        let argvalues = match call.args.get(1) {
+            Some(ast::Expr::List(ast::ExprList { elts, .. })) | Some(ast::Expr::Tuple(ast::ExprTuple { elts, .. })) => elts,
+            _ => return Vec::new(),
+        };
+        let argnames = self.extract_parametrize_argnames(decorators);
+        let explicit_ids: Vec<Option<String>> = call
+            .keywords
+            .iter()
+            .find(|keyword| keyword.arg.as_ref().map(|arg| arg.as_str()) == Some("ids"))
+            .and_then(|keyword| match &keyword.value {
+                ast::Expr::List(ast::ExprList { elts, .. }) | ast::Expr::Tuple(ast::ExprTuple { elts, .. }) => Some(elts),
+                _ => None,
+            })
+            .map(|elts| {
+                elts.iter()
+                    .map(|elt| match elt {
+                        ast::Expr::Constant(c) => c.value.as_str().cloned(),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        argvalues
+            .iter()
+            .enumerate()
+            .map(|(index, argvalue)| {
+                let mut marks = Vec::new();
+                let mut param_id = None;
+                let values: Vec<&ast::Expr> = match self.param_call(argvalue) {
+                    Some(param) => {
+                        for keyword in &param.keywords {
+                            match keyword.arg.as_ref().map(|arg| arg.as_str()) {
+                                Some("marks") => marks = match &keyword.value {
+                                    ast::Expr::List(ast::ExprList { elts, .. })
+                                    | ast::Expr::Tuple(ast::ExprTuple { elts, .. }) => self.extract_markers(elts),
+                                    other => self.extract_markers(std::slice::from_ref(other)),
+                                },
+                                Some("id") => {
+                                    if let ast::Expr::Constant(c) = &keyword.value {
+                                        param_id = c.value.as_str().cloned();
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                        param.args.iter().collect()
+                    }
+                    None => match argvalue {
+                        ast::Expr::List(ast::ExprList { elts, .. }) | ast::Expr::Tuple(ast::ExprTuple { elts, .. })
+                            if argnames.len() > 1 =>
+                        {
+                            elts.iter().collect()
+                        }
+                        other => vec![other],
+                    },
+                };
+
+                let id = param_id
+                    .or_else(|| explicit_ids.get(index).cloned().flatten())
+                    .unwrap_or_else(|| {
+                        values
+                            .iter()
+                            .enumerate()
+                            .map(|(position, value)| {
+                                let argname = argnames.get(position).map(String::as_str).unwrap_or("arg");
+                                self.param_value_id(value, argname, index)
+                            })
+                            .collect::<Vec<_>>()
+                            .join("-")
+                    });
+                ParamCase { id, marks }
+            })
+            .collect()
+    }
+
+    /// The `pytest.param(...)` / `param(...)` call of an argvalue, if it is one
+    fn param_call<'a>(&self, expr: &'a ast::Expr) -> Option<&'a ast::ExprCall> {
+        let ast::Expr::Call(call) = expr else {
+            return None;
+        };
+        let is_param = match call.func.as_ref() {
+            ast::Expr::Attribute(attr) => {
+                attr.attr.as_str() == "param"
+                    && matches!(attr.value.as_ref(), ast::Expr::Name(name) if name.id.as_str() == "pytest")
+            }
+            ast::Expr::Name(name) => name.id.as_str() == "param",
+            _ => false,
+        };
+        is_param.then_some(call)
+    }
+
+    /// pytest's id for one parametrized value: the value itself for simple constants,
+    /// `<argname><index>` otherwise
+    fn param_value_id(&self, value: &ast::Expr, argname: &str, index: usize) -> String {
+        match value {
+            ast::Expr::Constant(c) => match &c.value {
+                ast::Constant::Str(s) => s.clone(),
+                ast::Constant::Int(i) => i.to_string(),
+                ast::Constant::Float(f) if f.fract() == 0.0 && f.abs() < 1e16 => format!("{:.1}", f),
+                ast::Constant::Float(f) => f.to_string(),
+                ast::Constant::Bool(b) => if *b { "True" } else { "False" }.to_string(),
+                ast::Constant::None => "None".to_string(),
+                _ => format!("{}{}", argname, index),
+            },
+            _ => format!("{}{}", argname, index),
+        }
+    }
+
+    /// Whether any parametrize call routes values through fixtures:
+    /// `indirect=True` or a non-empty list of argument names
+    fn extract_parametrize_indirect(&self, decorators: &[ast::Expr]) -> bool {
+        self.marker_calls(decorators, "parametrize")
+            .into_iter()
+            .flat_map(|call| call.keywords.iter())
+            .filter(|keyword| keyword.arg.as_ref().map(|arg| arg.as_str()) == Some("indirect"))
+            .any(|keyword| match &keyword.value {
+                ast::Expr::Constant(c) => matches!(c.value, ast::Constant::Bool(true)),
+                ast::Expr::List(ast::ExprList { elts, .. }) | ast::Expr::Tuple(ast::ExprTuple { elts, .. }) => {
+                    !elts.is_empty()
+                }
+                _ => false,
+            })
+    }
+
+    /// Count `assert` statements in a body, descending into compound statements
+    /// but not into nested function or class definitions
+    fn count_assert_stmts(&self, body: &[ast::Stmt]) -> usize {
+        body.iter()
+            .map(|stmt| match stmt {
+                ast::Stmt::Assert(_) => 1,
+                ast::Stmt::If(s) => self.count_assert_stmts(&s.body) + self.count_assert_stmts(&s.orelse),
+                ast::Stmt::For(s) => self.count_assert_stmts(&s.body) + self.count_assert_stmts(&s.orelse),
+                ast::Stmt::AsyncFor(s) => self.count_assert_stmts(&s.body) + self.count_assert_stmts(&s.orelse),
+                ast::Stmt::While(s) => self.count_assert_stmts(&s.body) + self.count_assert_stmts(&s.orelse),
+                ast::Stmt::With(s) => self.count_assert_stmts(&s.body),
+                ast::Stmt::AsyncWith(s) => self.count_assert_stmts(&s.body),
+                ast::Stmt::Try(s) => {
+                    let handlers: usize = s
+                        .handlers
+                        .iter()
+                        .map(|ast::ExceptHandler::ExceptHandler(h)| self.count_assert_stmts(&h.body))
+                        .sum();
+                    handlers
+                        + self.count_assert_stmts(&s.body)
+                        + self.count_assert_stmts(&s.orelse)
+                        + self.count_assert_stmts(&s.finalbody)
+                }
+                ast::Stmt::Match(s) => s.cases.iter().map(|case| self.count_assert_stmts(&case.body)).sum(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Extract parametrize count from decorator list
+    /// Parses @pytest.mark.parametrize("arg", [val1, val2, ...]) to count parameter sets
+    /// This allows us to generate the correct number of test nodes WITHOUT importing Python code!
+    fn extract_parametrize_count(&self, decorators: &[ast::Expr], source: &SourceFile) -> Option<usize> {
+        let mut has_unknown = false;
+        for decorator in decorators {
+            let ast::Expr::Call(call) = decorator else {
+                continue;
+            };
+            // A marker factory (`@parametrize_ids()`) builds its argvalues out of sight
+            if let ast::Expr::Name(name) = call.func.as_ref() {
+                has_unknown |= source.marker_factories.get(name.id.as_str()).is_some_and(|marker| marker == "parametrize");
+                continue;
+            }
+            if self.decorator_mark_name(&call.func, source) != Some("parametrize") {
+                continue;
+            }
+
+            // Try to extract the parameter count from the second argument
+            // @pytest.mark.parametrize("arg", [val1, val2, val3]) -> count = 3
+            // @pytest.mark.parametrize("arg1,arg2", [(v1,v2), (v3,v4)]) -> count = 2
+            match call.args.get(1) {
+                Some(ast::Expr::List(list_expr)) => return Some(list_expr.elts.len()),
+                Some(ast::Expr::Tuple(tuple_expr)) => return Some(tuple_expr.elts.len()),
+                _ => has_unknown = true,
+            }
+        }
+
+        // Argvalues built at runtime: report the configured fallback
+        if has_unknown {
+            self.unknown_parametrize_count
+        } else {
+            None
+        }
+    }
+
+    /// Check if a function name indicates a test function
+    fn is_test_function(&self, name: &str) -> bool {
+        self.function_prefixes.iter().any(|prefix| name.starts_with(prefix.as_str()))
+    }
+
+    /// Check if a method name of a test class indicates a test
+    fn is_test_method(&self, name: &str) -> bool {
+        match &self.method_prefixes {
+            Some(prefixes) => prefixes.iter().any(|prefix| name.starts_with(prefix.as_str())),
+            None => self.is_test_function(name),
+        }
+    }
+
+    /// Check if a class name indicates a test class
+    fn is_test_class(&self, name: &str) -> bool {
+        self.class_prefixes.iter().any(|prefix| name.starts_with(prefix.as_str()))
+    }
+
+    /// Convert a single test item to a Python dict
+    fn item_to_python<'py>(&self, py: Python<'py>, item: &TestItem) -> PyResult<Bound<'py, PyDict>> {
+        let item_dict = PyDict::new(py);
+        item_dict.set_item("name", &item.name)?;
+        item_dict.set_item("qualname", &item.qualname)?;
+        item_dict.set_item("line", item.line_number)?;
+        item_dict.set_item("end_line", item.end_line)?;
+        item_dict.set_item("type", format!("{:?}", item.item_type))?;
+        item_dict.set_item("is_async", item.is_async)?;
+        item_dict.set_item("file_path", &item.file_path)?;
+
+        if let Some(ref class_name) = item.class_name {
+            item_dict.set_item("class", class_name)?;
+        }
+
+        // Add markers
+        let markers_list = PyList::empty(py);
+        for marker in &item.markers {
+            markers_list.append(marker)?;
+        }
+        item_dict.set_item("markers", markers_list)?;
+        item_dict.set_item("inherited_markers", &item.inherited_markers)?;
+        item_dict.set_item("requested_fixtures", &item.requested_fixtures)?;
+        if self.count_asserts {
+            item_dict.set_item("assert_count", item.assert_count)?;
+        }
+        if let Some(timeout) = item.timeout {
+            item_dict.set_item("timeout", timeout)?;
+        }
+        if self.capture_offsets {
+            item_dict.set_item("byte_start", item.byte_start)?;
+            item_dict.set_item("byte_end", item.byte_end)?;
+        }
+        if self.capture_signature {
+            item_dict.set_item("signature", &item.signature)?;
+        }
+        if let Some(ref order_key) = item.order_key {
+            item_dict.set_item("order_key", order_key)?;
+        }
+        if let Some(ref reason) = item.skip_reason {
+            item_dict.set_item("skip_reason", reason)?;
+        }
+
+        // Add parametrize count
+        if let Some(ref id) = item.parametrize_id {
+            item_dict.set_item("parametrize_id", id)?;
+        }
+        if let Some(count) = item.parametrize_count {
+            item_dict.set_item("parametrize_count", count)?;
+            item_dict.set_item("parametrize_indirect", item.parametrize_indirect)?;
+        }
+
+        Ok(item_dict)
+    }
+
+    /// Convert test items to Python dict structure with rich metadata
+    fn items_to_python(&self, py: Python, items: &[TestItem]) -> PyResult<Py<PyAny>> {
+        let result = PyDict::new(py);
+
+        // Group by file
+        let mut files_map: HashMap<String, Vec<&TestItem>> = HashMap::new();
+        for item in items {
+            files_map.entry(item.file_path.clone())
+                .or_default()
+                .push(item);
+        }
+
+        for (file_path, file_items) in files_map {
+            let items_list = PyList::empty(py);
+
+            for item in file_items {
+                items_list.append(self.item_to_python(py, item)?)?;
+            }
+
+            result.set_item(file_path, items_list)?;
+        }
+
+        Ok(result.into())
+    }
+
+    /// Convert one file's metadata to a Python dict (`mtime` and `items`)
+    fn file_metadata_to_python<'py>(&self, py: Python<'py>, file_meta: &FileMetadata) -> PyResult<Bound<'py, PyDict>> {
+        let file_dict = PyDict::new(py);
+        file_dict.set_item("mtime", file_meta.mtime)?;
+        if let Some(ref module) = file_meta.conditional_skip_module {
+            file_dict.set_item("conditional_skip_module", module)?;
+        }
+        if !file_meta.syntax_features.is_empty() {
+            file_dict.set_item("syntax_features", &file_meta.syntax_features)?;
+        }
+        if !file_meta.module_markers.is_empty() {
+            file_dict.set_item("module_markers", &file_meta.module_markers)?;
+        }
+        if !file_meta.imports.is_empty() {
+            file_dict.set_item("imports", &file_meta.imports)?;
+        }
+        if !file_meta.applicable_conftests.is_empty() {
+            file_dict.set_item("applicable_conftests", &file_meta.applicable_conftests)?;
+        }
+
+        let items_list = PyList::empty(py);
+        for item in &file_meta.test_items {
+            items_list.append(self.item_to_python(py, item)?)?;
+        }
+        file_dict.set_item("items", items_list)?;
+
+        Ok(file_dict)
+    }
+
+    /// Convert file metadata to Python dict structure
+    fn metadata_to_python(&self, py: Python, metadata: &[FileMetadata]) -> PyResult<Py<PyAny>> {
+        let result = PyDict::new(py);
+
+        for file_meta in metadata {
+            result.set_item(&file_meta.path, self.file_metadata_to_python(py, file_meta)?)?;
+        }
+
+        Ok(result.into())
+    }
+}
 
 /// A Python module implemented in Rust.
 #[pymodule]
 fn pytest_fastcollect(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<FastCollector>()?;
+    m.add_class::<CollectedItem>()?;
     m.add_function(wrap_pyfunction!(get_version, m)?)?;
     Ok(())
 }
 
-/// Get version information
-#[pyfunction]
-fn get_version() -> PyResult<String> {
-    Ok(env!("CARGO_PKG_VERSION").to_string())
-}
+/// Get version information
+#[pyfunction]
+fn get_version() -> PyResult<String> {
+    Ok(env!("CARGO_PKG_VERSION").to_string())
+}
+
+#[cfg(test)]
+#[allow(clippy::len_zero)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    /// Helper function to create a temporary test file
+    fn create_test_file(dir: &TempDir, name: &str, content: &str) -> PathBuf {
+        let file_path = dir.path().join(name);
+        let mut file = fs::File::create(&file_path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        file_path
+    }
+
+    #[test]
+    fn test_is_test_function_with_test_prefix() {
+        let collector = FastCollector::new("/tmp".to_string());
+        assert!(collector.is_test_function("test_foo"));
+        assert!(collector.is_test_function("test_bar_baz"));
+        assert!(collector.is_test_function("test"));
+    }
+
+    #[test]
+    fn test_is_test_function_without_test_prefix() {
+        let collector = FastCollector::new("/tmp".to_string());
+        assert!(!collector.is_test_function("foo_test"));
+        assert!(!collector.is_test_function("my_function"));
+        assert!(!collector.is_test_function("Test"));
+    }
+
+    #[test]
+    fn test_is_test_class_with_test_prefix() {
+        let collector = FastCollector::new("/tmp".to_string());
+        assert!(collector.is_test_class("TestFoo"));
+        assert!(collector.is_test_class("TestBarBaz"));
+        assert!(collector.is_test_class("Test"));
+    }
+
+    #[test]
+    fn test_is_test_class_without_test_prefix() {
+        let collector = FastCollector::new("/tmp".to_string());
+        assert!(!collector.is_test_class("FooTest"));
+        assert!(!collector.is_test_class("MyClass"));
+        assert!(!collector.is_test_class("test_foo"));
+    }
+
+    #[test]
+    fn test_is_test_file_with_valid_patterns() {
+        let collector = FastCollector::new("/tmp".to_string());
+        let test_file = PathBuf::from("test_foo.py");
+        let test_file2 = PathBuf::from("bar_test.py");
+
+        assert!(collector.is_test_file(&test_file));
+        assert!(collector.is_test_file(&test_file2));
+    }
+
+    #[test]
+    fn test_is_test_file_with_invalid_patterns() {
+        let collector = FastCollector::new("/tmp".to_string());
+        let non_test = PathBuf::from("foo.py");
+        let non_python = PathBuf::from("test_foo.txt");
+        let no_extension = PathBuf::from("test_foo");
+
+        assert!(!collector.is_test_file(&non_test));
+        assert!(!collector.is_test_file(&non_python));
+        assert!(!collector.is_test_file(&no_extension));
+    }
+
+    #[test]
+    fn test_should_ignore_common_directories() {
+        let collector = FastCollector::new("/tmp".to_string());
+
+        assert!(collector.should_ignore(&PathBuf::from(".git")));
+        assert!(collector.should_ignore(&PathBuf::from("__pycache__")));
+        assert!(collector.should_ignore(&PathBuf::from(".tox")));
+        assert!(collector.should_ignore(&PathBuf::from(".venv")));
+        assert!(collector.should_ignore(&PathBuf::from("venv")));
+    }
+
+    #[test]
+    fn test_should_not_ignore_regular_directories() {
+        let collector = FastCollector::new("/tmp".to_string());
+
+        assert!(!collector.should_ignore(&PathBuf::from("tests")));
+        assert!(!collector.should_ignore(&PathBuf::from("src")));
+        assert!(!collector.should_ignore(&PathBuf::from("my_module")));
+    }
+
+    #[test]
+    fn test_matches_wildcard_exact_match() {
+        let collector = FastCollector::new("/tmp".to_string());
+
+        assert!(collector.matches_wildcard("test.py", "test.py"));
+        assert!(!collector.matches_wildcard("test.py", "other.py"));
+    }
+
+    #[test]
+    fn test_matches_wildcard_prefix() {
+        let collector = FastCollector::new("/tmp".to_string());
+
+        assert!(collector.matches_wildcard("test_foo.py", "test_*.py"));
+        assert!(collector.matches_wildcard("test_bar_baz.py", "test_*.py"));
+        assert!(!collector.matches_wildcard("foo_test.py", "test_*.py"));
+    }
+
+    #[test]
+    fn test_matches_wildcard_suffix() {
+        let collector = FastCollector::new("/tmp".to_string());
+
+        assert!(collector.matches_wildcard("foo_test.py", "*_test.py"));
+        assert!(collector.matches_wildcard("bar_baz_test.py", "*_test.py"));
+        assert!(!collector.matches_wildcard("test_foo.py", "*_test.py"));
+    }
+
+    #[test]
+    fn test_matches_wildcard_middle() {
+        let collector = FastCollector::new("/tmp".to_string());
+
+        assert!(collector.matches_wildcard("test_foo_bar.py", "test_*_bar.py"));
+        assert!(!collector.matches_wildcard("test_foo.py", "test_*_bar.py"));
+    }
+
+    #[test]
+    fn test_parse_simple_test_function() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+def test_simple():
+    assert True
+"#;
+        let test_file = create_test_file(&temp_dir, "test_simple.py", content);
+
+        let items = collector.parse_test_file(&test_file).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "test_simple");
+        assert_eq!(items[0].class_name, None);
+    }
+
+    #[test]
+    fn test_parse_test_class() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+class TestFoo:
+    def test_method(self):
+        pass
+"#;
+        let test_file = create_test_file(&temp_dir, "test_class.py", content);
+
+        let items = collector.parse_test_file(&test_file).unwrap();
+        // Should have both the class and the method
+        assert!(items.len() >= 1);
+
+        // Find the class item
+        let class_item = items.iter().find(|i| i.name == "TestFoo");
+        assert!(class_item.is_some());
+
+        // Find the method item
+        let method_item = items.iter().find(|i| i.name == "test_method");
+        assert!(method_item.is_some());
+        assert_eq!(method_item.unwrap().class_name, Some("TestFoo".to_string()));
+    }
+
+    #[test]
+    fn test_parse_with_markers() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+import pytest
+
+@pytest.mark.slow
+def test_slow():
+    pass
+
+@pytest.mark.smoke
+@pytest.mark.regression
+def test_combined():
+    pass
+"#;
+        let test_file = create_test_file(&temp_dir, "test_markers.py", content);
+
+        let items = collector.parse_test_file(&test_file).unwrap();
+
+        // Find slow test
+        let slow_test = items.iter().find(|i| i.name == "test_slow").unwrap();
+        assert!(slow_test.markers.contains(&"slow".to_string()));
+
+        // Find combined test
+        let combined_test = items.iter().find(|i| i.name == "test_combined").unwrap();
+        assert!(combined_test.markers.contains(&"smoke".to_string()));
+        assert!(combined_test.markers.contains(&"regression".to_string()));
+    }
+
+    #[test]
+    fn test_parse_empty_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = "";
+        let test_file = create_test_file(&temp_dir, "test_empty.py", content);
+
+        let items = collector.parse_test_file(&test_file).unwrap();
+        assert_eq!(items.len(), 0);
+    }
+
+    #[test]
+    fn test_parse_non_test_functions() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+def helper_function():
+    pass
+
+def another_helper():
+    pass
+"#;
+        let test_file = create_test_file(&temp_dir, "test_helpers.py", content);
+
+        let items = collector.parse_test_file(&test_file).unwrap();
+        assert_eq!(items.len(), 0);
+    }
+
+    #[test]
+    fn test_parse_invalid_python() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = "this is not valid python syntax !!!";
+        let test_file = create_test_file(&temp_dir, "test_invalid.py", content);
+
+        let items = collector.parse_test_file(&test_file).unwrap();
+        // Should return empty vec on parse error, not crash
+        assert_eq!(items.len(), 0);
+    }
+
+    #[test]
+    fn test_find_test_files_in_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        // Create test files
+        create_test_file(&temp_dir, "test_one.py", "def test_one(): pass");
+        create_test_file(&temp_dir, "test_two.py", "def test_two(): pass");
+        create_test_file(&temp_dir, "helper.py", "def foo(): pass");
+
+        let test_files = collector.find_test_files();
+
+        assert_eq!(test_files.len(), 2);
+        assert!(test_files.iter().any(|p| p.file_name().unwrap() == "test_one.py"));
+        assert!(test_files.iter().any(|p| p.file_name().unwrap() == "test_two.py"));
+    }
+
+    #[test]
+    fn test_find_test_files_ignores_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        // Create test file
+        create_test_file(&temp_dir, "test_one.py", "def test_one(): pass");
+
+        // Create ignored directory with test file
+        let ignored_dir = temp_dir.path().join("__pycache__");
+        fs::create_dir(&ignored_dir).unwrap();
+        let mut ignored_file = fs::File::create(ignored_dir.join("test_cached.py")).unwrap();
+        ignored_file.write_all(b"def test_cached(): pass").unwrap();
+
+        let test_files = collector.find_test_files();
+
+        // Should only find test_one.py, not test_cached.py
+        assert_eq!(test_files.len(), 1);
+        assert_eq!(test_files[0].file_name().unwrap(), "test_one.py");
+    }
+
+    #[test]
+    fn test_extract_markers_pytest_mark() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+import pytest
+
+@pytest.mark.slow
+def test_foo():
+    pass
+"#;
+        let test_file = create_test_file(&temp_dir, "test_markers.py", content);
+        let items = collector.parse_test_file(&test_file).unwrap();
+
+        let test_item = items.iter().find(|i| i.name == "test_foo").unwrap();
+        assert_eq!(test_item.markers, vec!["slow"]);
+    }
+
+    #[test]
+    fn test_extract_markers_multiple() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+import pytest
+
+@pytest.mark.slow
+@pytest.mark.integration
+@pytest.mark.smoke
+def test_foo():
+    pass
+"#;
+        let test_file = create_test_file(&temp_dir, "test_markers.py", content);
+        let items = collector.parse_test_file(&test_file).unwrap();
+
+        let test_item = items.iter().find(|i| i.name == "test_foo").unwrap();
+        assert!(test_item.markers.contains(&"slow".to_string()));
+        assert!(test_item.markers.contains(&"integration".to_string()));
+        assert!(test_item.markers.contains(&"smoke".to_string()));
+    }
+
+    #[test]
+    fn test_line_numbers_are_correct() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+# Line 1
+# Line 2
+def test_one():  # Line 3
+    pass
+
+# Line 6
+def test_two():  # Line 7
+    pass
+"#;
+        let test_file = create_test_file(&temp_dir, "test_lines.py", content);
+        let items = collector.parse_test_file(&test_file).unwrap();
+
+        let test_one = items.iter().find(|i| i.name == "test_one").unwrap();
+        let test_two = items.iter().find(|i| i.name == "test_two").unwrap();
+
+        // Line numbers start at 0 in the AST, but we report 1-indexed
+        assert!(test_one.line_number > 0);
+        assert!(test_two.line_number > test_one.line_number);
+    }
+
+    #[test]
+    fn test_report_empty_after_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.set_report_empty_after_filter(true);
+
+        let content = r#"
+import pytest
+
+@pytest.mark.fast
+def test_quick():
+    pass
+"#;
+        let unmatched = create_test_file(&temp_dir, "test_unmatched.py", content);
+        create_test_file(&temp_dir, "test_matched.py", "import pytest\n\n@pytest.mark.slow\ndef test_slow():\n    pass\n");
+        create_test_file(&temp_dir, "test_nothing.py", "def helper():\n    pass\n");
+
+        let filter = TestFilter::new(None, Some("slow".to_string()));
+        let metadata = collector.collect_filtered(&filter);
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(metadata[0].test_items[0].name, "test_slow");
+
+        // Only the file that had tests rejected by the filter is reported
+        assert_eq!(
+            collector.get_empty_after_filter(),
+            vec![unmatched.to_string_lossy().to_string()]
+        );
+    }
+
+    #[test]
+    fn test_collect_node_ids() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+@pytest.mark.slow
+def test_function():
+    pass
+
+class TestGroup:
+    def test_method(self):
+        pass
+"#;
+        create_test_file(&temp_dir, "test_ids.py", content);
+
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        assert_eq!(
+            collector.filtered_node_ids(&TestFilter::new(None, None)),
+            vec!["test_ids.py::test_function", "test_ids.py::TestGroup::test_method"]
+        );
+        let filter = TestFilter::new(None, Some("slow".to_string()));
+        assert_eq!(collector.filtered_node_ids(&filter), vec!["test_ids.py::test_function"]);
+    }
+
+    #[test]
+    fn test_collect_unicode_named_tests() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        // Second function is spelled with a decomposed "e" + combining acute accent
+        let content = "def test_café():\n    pass\n\ndef test_nai\u{0308}ve():\n    pass\n\ndef test_plain():\n    pass\n";
+        create_test_file(&temp_dir, "test_unicode.py", content);
+
+        let names = |filter: &TestFilter| -> Vec<String> {
+            collector
+                .collect_filtered(filter)
+                .into_iter()
+                .flat_map(|file_meta| file_meta.test_items)
+                .map(|item| item.name)
+                .collect()
+        };
+        assert_eq!(names(&TestFilter::new(Some("CAFÉ".to_string()), None)), vec!["test_café".to_string()]);
+
+        // Python normalizes identifiers to NFKC, so the name uses the composed form
+        assert_eq!(names(&TestFilter::new(Some("naïve".to_string()), None)), vec!["test_na\u{00ef}ve".to_string()]);
+    }
+
+    #[test]
+    fn test_parallel_chunk_size_does_not_change_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        for i in 0..20 {
+            let content = format!("def test_{i}_a():\n    pass\n\ndef test_{i}_b():\n    pass\n");
+            create_test_file(&temp_dir, &format!("test_file_{i}.py"), &content);
+        }
+
+        let filter = TestFilter::new(None, None);
+        let baseline = collector.filtered_node_ids(&filter);
+        assert_eq!(baseline.len(), 40);
+
+        for chunk_size in [1, 3, 7, 64] {
+            collector.set_parallel_chunk_size(chunk_size);
+            assert_eq!(collector.filtered_node_ids(&filter), baseline);
+        }
+    }
+
+    #[test]
+    fn test_collect_unmarked_uses_effective_markers() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+import pytest
+
+def test_unmarked():
+    pass
+
+@pytest.mark.unit
+def test_marked():
+    pass
+
+@pytest.mark.integration
+class TestMarkedClass:
+    def test_inherits_class_marker(self):
+        pass
+
+class TestPlainClass:
+    def test_unmarked_method(self):
+        pass
+"#;
+        create_test_file(&temp_dir, "test_mixed.py", content);
+
+        let module_marked = r#"
+import pytest
+
+pytestmark = [pytest.mark.unit]
+
+def test_inherits_module_marker():
+    pass
+"#;
+        create_test_file(&temp_dir, "test_module_marked.py", module_marked);
+
+        let mut node_ids = collector.filtered_node_ids(&TestFilter::unmarked());
+        node_ids.sort();
+        assert_eq!(
+            node_ids,
+            vec![
+                "test_mixed.py::TestPlainClass::test_unmarked_method".to_string(),
+                "test_mixed.py::test_unmarked".to_string(),
+            ]
+        );
+
+        // Inherited markers also take part in -m filtering
+        let filter = TestFilter::new(None, Some("integration".to_string()));
+        assert_eq!(
+            collector.filtered_node_ids(&filter),
+            vec!["test_mixed.py::TestMarkedClass::test_inherits_class_marker".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_respect_all_limits_collection() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+__all__ = ["test_exported", "TestExported"]
+
+def test_exported():
+    pass
+
+def test_hidden():
+    pass
+
+class TestExported:
+    def test_method(self):
+        pass
+
+class TestHidden:
+    def test_method(self):
+        pass
+"#;
+        create_test_file(&temp_dir, "test_all.py", content);
+        create_test_file(&temp_dir, "test_no_all.py", "def test_free():\n    pass\n");
+
+        let filter = TestFilter::new(None, None);
+        assert_eq!(collector.filtered_node_ids(&filter).len(), 5);
+
+        collector.set_respect_all(true);
+        let mut node_ids = collector.filtered_node_ids(&filter);
+        node_ids.sort();
+        assert_eq!(
+            node_ids,
+            vec![
+                "test_all.py::TestExported::test_method".to_string(),
+                "test_all.py::test_exported".to_string(),
+                "test_no_all.py::test_free".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_msgpack_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+import pytest
+
+@pytest.mark.parametrize("x", [1, 2])
+def test_param(x):
+    pass
+
+class TestFoo:
+    @pytest.mark.slow
+    def test_method(self):
+        pass
+"#;
+        create_test_file(&temp_dir, "test_msgpack.py", content);
+
+        let metadata = collector.collect_filtered(&TestFilter::new(None, None));
+        let bytes = encode_msgpack(&metadata).unwrap();
+        let decoded: Vec<FileMetadata> = rmp_serde::from_slice(&bytes).unwrap();
+
+        assert_eq!(decoded, metadata);
+        assert_eq!(decoded[0].test_items.len(), 3);
+    }
+
+    #[test]
+    fn test_usefixtures_merged_into_requested_fixtures() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+import pytest
+
+@pytest.mark.usefixtures("db", "client")
+@pytest.mark.parametrize("value", [1, 2])
+def test_api(tmp_path, value, retries=3):
+    pass
+
+@pytest.mark.usefixtures("app")
+class TestViews:
+    def test_index(self, client):
+        pass
+"#;
+        let test_file = create_test_file(&temp_dir, "test_fixtures.py", content);
+        let items = collector.parse_test_file(&test_file).unwrap();
+
+        let test_api = items.iter().find(|i| i.name == "test_api").unwrap();
+        assert_eq!(test_api.requested_fixtures, vec!["tmp_path", "db", "client"]);
+
+        let test_index = items.iter().find(|i| i.name == "test_index").unwrap();
+        assert_eq!(test_index.requested_fixtures, vec!["client", "app"]);
+    }
+
+    #[test]
+    fn test_resolve_and_collect_dotted_module() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let package_dir = temp_dir.path().join("mypkg").join("sub");
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(package_dir.join("test_x.py"), "def test_nested():\n    pass\n").unwrap();
+
+        let path = collector.resolve_module("mypkg.sub.test_x").unwrap();
+        assert_eq!(path, package_dir.join("test_x.py"));
+        let items = collector.parse_test_file(&path).unwrap();
+        assert_eq!(items[0].name, "test_nested");
+
+        assert!(collector.resolve_module("mypkg.sub.test_missing").is_none());
+        assert!(collector.resolve_module("mypkg..test_x").is_none());
+    }
+
+    #[test]
+    fn test_collect_module_uses_file_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let package_dir = temp_dir.path().join("mypkg");
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(package_dir.join("test_x.py"), "def test_cached():\n    pass\n").unwrap();
+        let path = collector.resolve_module("mypkg.test_x").unwrap();
+
+        let first = collector.collect_module_file(&path);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 1);
+        assert!(collector.cache.read().unwrap().contains_key(path.to_str().unwrap()));
+
+        let second = collector.collect_module_file(&path);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 1);
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 1);
+        assert_eq!(second, first);
+        assert_eq!(second.test_items[0].name, "test_cached");
+    }
+
+    #[test]
+    fn test_nested_test_classes_compose_class_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+class TestAPI:
+    class TestGet:
+        def test_status(self):
+            pass
+
+        class TestHeaders:
+            def test_content_type(self):
+                pass
+
+    class TestPost:
+        def test_create(self):
+            pass
+"#;
+        let test_file = create_test_file(&temp_dir, "test_nested.py", content);
+        let items = collector.parse_test_file(&test_file).unwrap();
+
+        let test_status = items.iter().find(|i| i.name == "test_status").unwrap();
+        assert_eq!(test_status.class_name.as_deref(), Some("TestAPI::TestGet"));
+        let test_content_type = items.iter().find(|i| i.name == "test_content_type").unwrap();
+        assert_eq!(test_content_type.class_name.as_deref(), Some("TestAPI::TestGet::TestHeaders"));
+
+        let mut node_ids = collector.filtered_node_ids(&TestFilter::new(None, None));
+        node_ids.sort();
+        assert_eq!(
+            node_ids,
+            vec![
+                "test_nested.py::TestAPI::TestGet::TestHeaders::test_content_type".to_string(),
+                "test_nested.py::TestAPI::TestGet::test_status".to_string(),
+                "test_nested.py::TestAPI::TestPost::test_create".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collect_streaming_reports_every_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let mut expected = Vec::new();
+        for i in 0..10 {
+            let path = create_test_file(&temp_dir, &format!("test_stream_{i}.py"), "def test_one():\n    pass\n");
+            expected.push(path.to_string_lossy().to_string());
+        }
+        create_test_file(&temp_dir, "test_empty.py", "def helper():\n    pass\n");
+
+        let caller = std::thread::current().id();
+        let mut reported = Vec::new();
+        collector.collect_streaming(
+            &TestFilter::new(None, None),
+            |file_meta| {
+                assert_eq!(std::thread::current().id(), caller);
+                reported.push(file_meta.path);
+            },
+            |_| {},
+        );
+
+        reported.sort();
+        expected.sort();
+        assert_eq!(reported, expected);
+    }
+
+    #[test]
+    fn test_importorskip_marks_file_conditionally_skipped() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+import pytest
+
+pytest.importorskip("numpy")
+
+def test_array():
+    pass
+"#;
+        let test_file = create_test_file(&temp_dir, "test_numpy.py", content);
+        create_test_file(&temp_dir, "test_plain.py", "def test_plain():\n    pass\n");
+
+        let parsed = collector.parse_file(&test_file).unwrap();
+        assert_eq!(parsed.conditional_skip_module.as_deref(), Some("numpy"));
+        assert_eq!(parsed.items.len(), 1);
+
+        let metadata = collector.collect_filtered(&TestFilter::new(None, None));
+        let plain = metadata.iter().find(|m| m.path.ends_with("test_plain.py")).unwrap();
+        assert_eq!(plain.conditional_skip_module, None);
+        let numpy = metadata.iter().find(|m| m.path.ends_with("test_numpy.py")).unwrap();
+        assert_eq!(numpy.conditional_skip_module.as_deref(), Some("numpy"));
+    }
+
+    #[test]
+    fn test_qualname_for_doubly_nested_method() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+class TestAPI:
+    class TestGet:
+        def test_status(self):
+            pass
+
+def test_free():
+    pass
+"#;
+        let test_file = create_test_file(&temp_dir, "test_qualname.py", content);
+        let items = collector.parse_test_file(&test_file).unwrap();
+
+        let test_status = items.iter().find(|i| i.name == "test_status").unwrap();
+        assert_eq!(test_status.qualname, "TestAPI.TestGet.test_status");
+        let test_get = items.iter().find(|i| i.name == "TestGet").unwrap();
+        assert_eq!(test_get.qualname, "TestAPI.TestGet");
+        let test_free = items.iter().find(|i| i.name == "test_free").unwrap();
+        assert_eq!(test_free.qualname, "test_free");
+    }
+
+    #[test]
+    fn test_parse_error_policies() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        create_test_file(&temp_dir, "test_ok.py", "def test_ok():\n    pass\n");
+        let broken = create_test_file(&temp_dir, "test_broken.py", "def test_ok():\n    pass\n\ndef test_bad(:\n    pass\n");
+        let filter = TestFilter::new(None, None);
+
+        // Default "warn": the broken file is dropped but recorded with its position
+        assert_eq!(collector.parse_error_policy, ParseErrorPolicy::Warn);
+        assert_eq!(collector.filtered_node_ids(&filter), vec!["test_ok.py::test_ok".to_string()]);
+        let errors = collector.parse_errors.read().unwrap().clone();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].file_path, broken.to_string_lossy());
+        assert_eq!(errors[0].line, 4);
+        assert_eq!(collector.warnings.read().unwrap().len(), 1);
+
+        // "skip": silently dropped, even when served from the cache
+        collector.parse_error_policy = ParseErrorPolicy::Skip;
+        assert_eq!(collector.filtered_node_ids(&filter).len(), 1);
+        assert!(collector.parse_errors.read().unwrap().is_empty());
+        assert!(collector.warnings.read().unwrap().is_empty());
+
+        // "error": recorded so the Python entry points raise SyntaxError
+        collector.parse_error_policy = ParseErrorPolicy::Error;
+        collector.filtered_node_ids(&filter);
+        let errors = collector.parse_errors.read().unwrap().clone();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().starts_with(&format!("{}:4:", broken.to_string_lossy())));
+        assert!(collector.warnings.read().unwrap().is_empty());
+
+        assert_eq!(ParseErrorPolicy::from_name("error"), Some(ParseErrorPolicy::Error));
+        assert_eq!(ParseErrorPolicy::from_name("explode"), None);
+    }
+
+    #[test]
+    fn test_error_policy_stops_at_first_parse_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let broken = create_test_file(&temp_dir, "test_broken.py", "def test_bad(:\n    pass\n");
+        let later = create_test_file(&temp_dir, "test_later.py", "def test_later():\n    pass\n");
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.parse_error_policy = ParseErrorPolicy::Error;
+        let filter = TestFilter::new(None, None);
+
+        assert!(collector.collect_file_filtered(&broken, &filter).is_none());
+        assert!(collector.collect_file_filtered(&later, &filter).is_none());
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 1);
+        let error = collector.fatal_parse_error().unwrap();
+        assert_eq!(error.file_path, broken.to_string_lossy());
+        assert_eq!(error.line, 1);
+
+        // A new collection starts over
+        collector.begin_collection();
+        assert!(collector.fatal_parse_error().is_none());
+        assert!(collector.collect_file_filtered(&later, &filter).is_some());
+    }
+
+    #[test]
+    fn test_skip_older_than_cache_serves_from_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let tests_dir = temp_dir.path().join("tests");
+        fs::create_dir(&tests_dir).unwrap();
+        let cache_file = temp_dir.path().join("cache.json");
+        let root = tests_dir.to_str().unwrap().to_string();
+
+        let first = tests_dir.join("test_one.py");
+        fs::write(&first, "def test_one():\n    pass\n").unwrap();
+        fs::write(tests_dir.join("test_two.py"), "def test_two():\n    pass\n").unwrap();
+
+        let writer = FastCollector::new(root.clone());
+        *writer.cache_path.write().unwrap() = Some(cache_file.clone());
+        writer.collect_filtered(&TestFilter::new(None, None));
+        assert_eq!(writer.stats.files_parsed.load(Ordering::Relaxed), 2);
+
+        // Rewrite and backdate one file: its mtime no longer matches the entry but is older than
+        // the cache file
+        fs::write(&first, "def test_one():\n    pass\n\ndef test_extra():\n    pass\n").unwrap();
+        let backdated = SystemTime::now() - std::time::Duration::from_secs(3600);
+        fs::File::options().write(true).open(&first).unwrap().set_modified(backdated).unwrap();
+
+        // Default validation re-parses it (on a copy, so the original cache stays untouched)
+        let cache_copy = temp_dir.path().join("cache_copy.json");
+        fs::copy(&cache_file, &cache_copy).unwrap();
+        let collector = FastCollector::new(root.clone());
+        *collector.cache_path.write().unwrap() = Some(cache_copy);
+        collector.load_cache();
+        assert_eq!(collector.filtered_node_ids(&TestFilter::new(None, None)).len(), 3);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 1);
+
+        // Trusted from the walk's mtime alone: the rewritten content and size are never looked at
+        let mut collector = FastCollector::new(root);
+        collector.set_skip_older_than_cache(true);
+        *collector.cache_path.write().unwrap() = Some(cache_file);
+        collector.load_cache();
+        let mut node_ids = collector.filtered_node_ids(&TestFilter::new(None, None));
+        node_ids.sort();
+        assert_eq!(node_ids, vec!["test_one.py::test_one", "test_two.py::test_two"]);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 0);
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_item_columns_have_equal_length() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let content = r#"
+import pytest
+
+@pytest.mark.slow
+def test_one():
+    pass
+
+class TestFoo:
+    def test_two(self):
+        pass
+"#;
+        create_test_file(&temp_dir, "test_columns.py", content);
+        create_test_file(&temp_dir, "test_more.py", "def test_three():\n    pass\n");
+
+        let metadata = collector.collect_filtered(&TestFilter::new(None, None));
+        let item_count: usize = metadata.iter().map(|m| m.test_items.len()).sum();
+        assert_eq!(item_count, 4);
+
+        let columns = ItemColumns::from_metadata(&metadata);
+        for len in [
+            columns.file_path.len(),
+            columns.name.len(),
+            columns.class_name.len(),
+            columns.line.len(),
+            columns.item_type.len(),
+            columns.markers.len(),
+        ] {
+            assert_eq!(len, item_count);
+        }
+
+        let row = columns.name.iter().position(|n| n == "test_two").unwrap();
+        assert_eq!(columns.class_name[row].as_deref(), Some("TestFoo"));
+        assert_eq!(columns.item_type[row], "Method");
+    }
+
+    #[test]
+    fn test_collect_lines_selects_overlapping_items() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+def test_first():
+    assert True
+
+@pytest.mark.slow
+def test_second():
+    x = 1
+    assert x
+
+def test_third():
+    pass
+"#;
+        let file = create_test_file(&temp_dir, "test_range.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let metadata = collector.collect_lines(&file, 7, 7);
+        assert_eq!(metadata.test_items.len(), 1);
+        let item = &metadata.test_items[0];
+        assert_eq!(item.name, "test_second");
+        assert_eq!(item.line_number, 6);
+        assert_eq!(item.end_line, 8);
+
+        let names: Vec<_> = collector
+            .collect_lines(&file, 3, 10)
+            .test_items
+            .into_iter()
+            .map(|item| item.name)
+            .collect();
+        assert_eq!(names, vec!["test_first", "test_second", "test_third"]);
+    }
+
+    #[test]
+    fn test_reset_clears_collection_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        create_test_file(&temp_dir, "test_ok.py", "def test_ok():\n    pass\n");
+        create_test_file(&temp_dir, "test_broken.py", "def test_bad(:\n    pass\n");
+        let filter = TestFilter::new(None, None);
+
+        collector.collect_filtered(&filter);
+        collector.collect_filtered(&filter);
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 2);
+        assert_eq!(collector.warnings.read().unwrap().len(), 1);
+
+        collector.reset();
+        assert!(collector.cache.read().unwrap().is_empty());
+        assert!(collector.warnings.read().unwrap().is_empty());
+        assert!(collector.parse_errors.read().unwrap().is_empty());
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 0);
+
+        // The next collection starts from a cold cache
+        let files = collector.collect_filtered(&filter);
+        assert_eq!(files.len(), 1);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 2);
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_rootdir_above_root_path_prefixes_node_ids() {
+        let temp_dir = TempDir::new().unwrap();
+        let tests_dir = temp_dir.path().join("tests");
+        fs::create_dir(&tests_dir).unwrap();
+        fs::write(tests_dir.join("test_api.py"), "def test_get():\n    pass\n").unwrap();
+
+        let mut collector = FastCollector::new(tests_dir.to_str().unwrap().to_string());
+        let filter = TestFilter::new(None, None);
+        assert_eq!(collector.filtered_node_ids(&filter), vec!["test_api.py::test_get".to_string()]);
+
+        collector.set_rootdir(temp_dir.path().to_str().unwrap().to_string());
+        assert_eq!(collector.filtered_node_ids(&filter), vec!["tests/test_api.py::test_get".to_string()]);
+        let files = collector.collect_filtered(&filter);
+        assert_eq!(Path::new(&files[0].test_items[0].file_path), Path::new("tests/test_api.py"));
+    }
+
+    #[test]
+    fn test_count_asserts() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+def test_two_asserts():
+    assert 1
+    if True:
+        assert 2
+
+def test_no_asserts():
+    pass
+"#;
+        let file = create_test_file(&temp_dir, "test_asserts.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.set_count_asserts(true);
+
+        let items = collector.parse_test_file(&file).unwrap();
+        assert_eq!(items[0].name, "test_two_asserts");
+        assert_eq!(items[0].assert_count, 2);
+        assert_eq!(items[1].name, "test_no_asserts");
+        assert_eq!(items[1].assert_count, 0);
+    }
+
+    #[test]
+    fn test_switching_hash_algorithm_forces_reparse() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(&temp_dir, "test_one.py", "def test_one():\n    pass\n");
+        create_test_file(&temp_dir, "test_two.py", "def test_two():\n    pass\n");
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let filter = TestFilter::new(None, None);
+
+        collector.hash_algorithm = Some(HashAlgorithm::Blake3);
+        collector.collect_filtered(&filter);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 2);
+        collector.collect_filtered(&filter);
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 2);
+
+        collector.hash_algorithm = Some(HashAlgorithm::Xxhash);
+        collector.collect_filtered(&filter);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 2);
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 0);
+        let cache = collector.cache.read().unwrap();
+        assert!(cache.values().all(|entry| entry.content_hash.as_ref().unwrap().starts_with("xxhash:")));
+    }
+
+    #[test]
+    fn test_hash_match_records_new_mtime() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = create_test_file(&temp_dir, "test_touched.py", "def test_touched():\n    pass\n");
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.hash_algorithm = Some(HashAlgorithm::Blake3);
+        let filter = TestFilter::new(None, None);
+        collector.collect_filtered(&filter);
+        let key = file.to_string_lossy().to_string();
+        let expected = HashAlgorithm::Blake3.digest(&fs::read(&file).unwrap());
+        assert_eq!(collector.cache.read().unwrap()[&key].content_hash.as_ref(), Some(&expected));
+
+        let touched = SystemTime::now() + std::time::Duration::from_secs(60);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(touched).unwrap();
+        collector.collect_filtered(&filter);
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 1);
+        assert_eq!(collector.cache.read().unwrap()[&key].mtime, file_mtime(&file));
+
+        // Back to mtime-only validation: the recorded mtime still matches
+        collector.hash_algorithm = None;
+        collector.collect_filtered(&filter);
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_marker_factory_resolved() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+def mark_slow():
+    return pytest.mark.slow
+
+def mark_dynamic(name):
+    marker = getattr(pytest.mark, name)
+    return marker
+
+@mark_slow()
+def test_factory():
+    pass
+
+@mark_dynamic("fast")
+def test_unresolvable():
+    pass
+"#;
+        let file = create_test_file(&temp_dir, "test_factory.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let items = collector.parse_test_file(&file).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].markers, vec!["slow".to_string()]);
+        assert!(items[1].markers.is_empty());
+    }
+
+    #[test]
+    fn test_collect_containers_returns_only_classes() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let content = r#"
+def test_function():
+    pass
+
+class TestOuter:
+    def test_method(self):
+        pass
+
+    class TestInner:
+        def test_nested(self):
+            pass
+"#;
+        create_test_file(&temp_dir, "test_tree.py", content);
+        create_test_file(&temp_dir, "test_flat.py", "def test_flat():\n    pass\n");
+
+        let mut files = collector.collect_container_metadata();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(files.len(), 2);
+        assert!(files[0].test_items.is_empty());
+        let names: Vec<_> = files[1].test_items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["TestOuter", "TestInner"]);
+        assert!(files[1].test_items.iter().all(|item| item.item_type == TestItemType::Class));
+    }
+
+    #[test]
+    fn test_save_cache_is_byte_stable() {
+        let temp_dir = TempDir::new().unwrap();
+        let tests_dir = temp_dir.path().join("tests");
+        fs::create_dir(&tests_dir).unwrap();
+        for i in 0..20 {
+            fs::write(tests_dir.join(format!("test_{}.py", i)), "def test_it():\n    pass\n").unwrap();
+        }
+        let cache_file = temp_dir.path().join("cache.json");
+
+        let collector = FastCollector::new(tests_dir.to_str().unwrap().to_string());
+        *collector.cache_path.write().unwrap() = Some(cache_file.clone());
+        collector.collect_filtered(&TestFilter::new(None, None));
+        let first = fs::read(&cache_file).unwrap();
+
+        // A fresh collector reloads the same entries into a new HashMap before saving again
+        let reloaded = FastCollector::new(tests_dir.to_str().unwrap().to_string());
+        *reloaded.cache_path.write().unwrap() = Some(cache_file.clone());
+        reloaded.load_cache();
+        reloaded.save_cache().unwrap();
+        assert_eq!(fs::read(&cache_file).unwrap(), first);
+    }
+
+    #[test]
+    fn test_exclude_classes_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let content = r#"
+def test_free():
+    pass
+
+class TestIntegration:
+    def test_slow(self):
+        pass
+
+class TestUnit:
+    def test_fast(self):
+        pass
+"#;
+        create_test_file(&temp_dir, "test_classes.py", content);
+        let filter = TestFilter {
+            exclude_classes: vec!["TestIntegration".to_string()],
+            ..TestFilter::new(None, None)
+        };
+
+        let node_ids = collector.filtered_node_ids(&filter);
+        assert_eq!(
+            node_ids,
+            vec!["test_classes.py::test_free".to_string(), "test_classes.py::TestUnit::test_fast".to_string()]
+        );
+
+        let glob = TestFilter {
+            exclude_classes: vec!["*Unit".to_string()],
+            ..TestFilter::new(None, None)
+        };
+        let names: Vec<_> = collector.collect_filtered(&glob)[0]
+            .test_items
+            .iter()
+            .map(|item| item.name.clone())
+            .collect();
+        assert_eq!(names, vec!["test_free", "TestIntegration", "test_slow"]);
+    }
+
+    #[test]
+    fn test_parametrize_indirect_recorded() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+@pytest.mark.parametrize("user", ["alice", "bob", "carol"], indirect=True)
+def test_indirect(user):
+    pass
+
+@pytest.mark.parametrize("user,role", [("a", 1), ("b", 2)], indirect=["user"])
+def test_indirect_names(user, role):
+    pass
+
+@pytest.mark.parametrize("x", [1, 2], indirect=False)
+def test_direct(x):
+    pass
+"#;
+        let file = create_test_file(&temp_dir, "test_indirect.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let items = collector.parse_test_file(&file).unwrap();
+        assert!(items[0].parametrize_indirect);
+        assert_eq!(items[0].parametrize_count, Some(3));
+        assert!(items[1].parametrize_indirect);
+        assert_eq!(items[1].parametrize_count, Some(2));
+        assert!(!items[2].parametrize_indirect);
+        assert_eq!(items[2].parametrize_count, Some(2));
+    }
+
+    #[test]
+    fn test_item_records_match_item_fields() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+class TestAPI:
+    @pytest.mark.slow
+    def test_get(self):
+        pass
+"#;
+        let file = create_test_file(&temp_dir, "test_records.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let items = collector.parse_test_file(&file).unwrap();
+        let (file_path, name, class, line, item_type, markers) = items[1].to_record();
+        assert_eq!(file_path, file.to_string_lossy());
+        assert_eq!(name, "test_get");
+        assert_eq!(class.as_deref(), Some("TestAPI"));
+        assert_eq!(line, 6);
+        assert_eq!(item_type, "Method");
+        assert_eq!(markers, vec!["slow".to_string()]);
+        assert_eq!(items[0].to_record().4, "Class");
+    }
+
+    #[test]
+    fn test_collect_records_match_dict_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "import pytest\n\n@pytest.mark.smoke\ndef test_free():\n    pass\n\nclass TestAPI:\n    @pytest.mark.slow\n    def test_get(self):\n        pass\n";
+        let file = create_test_file(&temp_dir, "test_records.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let file_path = file.to_string_lossy().to_string();
+
+        let records = collector.item_records();
+        assert_eq!(
+            records,
+            vec![
+                (file_path.clone(), "test_free".to_string(), None, 4, "Function".to_string(), vec!["smoke".to_string()]),
+                (file_path.clone(), "TestAPI".to_string(), None, 7, "Class".to_string(), vec![]),
+                (
+                    file_path.clone(),
+                    "test_get".to_string(),
+                    Some("TestAPI".to_string()),
+                    9,
+                    "Method".to_string(),
+                    vec!["slow".to_string()]
+                ),
+            ]
+        );
+
+        // Field for field the same as the dict-based (JSON) output
+        let json = serde_json::to_value(collector.collect_filtered(&TestFilter::new(None, None))).unwrap();
+        let dict_records: Vec<ItemRecord> = json[0]["test_items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| {
+                (
+                    item["file_path"].as_str().unwrap().to_string(),
+                    item["name"].as_str().unwrap().to_string(),
+                    item["class_name"].as_str().map(str::to_string),
+                    item["line_number"].as_u64().unwrap() as usize,
+                    item["item_type"].as_str().unwrap().to_string(),
+                    serde_json::from_value(item["markers"].clone()).unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(dict_records, records);
+    }
+
+    #[test]
+    fn test_exclude_markers_hard_drops_items_everywhere() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+@pytest.mark.manual
+def test_manual():
+    pass
+
+def test_auto():
+    pass
+
+@pytest.mark.manual
+class TestManualSuite:
+    def test_inside(self):
+        pass
+"#;
+        create_test_file(&temp_dir, "test_lanes.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let filter = TestFilter::new(None, None);
+        assert_eq!(collector.filtered_node_ids(&filter).len(), 3);
+
+        collector.set_exclude_markers_hard(vec!["manual".to_string()]);
+        assert_eq!(collector.filtered_node_ids(&filter), vec!["test_lanes.py::test_auto".to_string()]);
+        let files = collector.collect_filtered(&filter);
+        assert_eq!(files[0].test_items.len(), 1);
+        let cache = collector.cache.read().unwrap();
+        assert!(cache.values().all(|entry| entry.parsed.items.iter().all(|item| item.name == "test_auto")));
+    }
+
+    #[test]
+    fn test_fastcollect_toml_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = r#"
+test_patterns = ["check_*.py"]
+ignore_patterns = ["skipped"]
+function_prefixes = ["check"]
+class_prefixes = ["Check"]
+cache_path = ".cache/fastcollect.json"
+"#;
+        create_test_file(&temp_dir, ".fastcollect.toml", config);
+        let content = "def check_a():\n    pass\n\ndef test_b():\n    pass\n\nclass CheckSuite:\n    def check_c(self):\n        pass\n";
+        create_test_file(&temp_dir, "check_things.py", content);
+        create_test_file(&temp_dir, "test_things.py", "def test_ignored():\n    pass\n");
+        fs::create_dir(temp_dir.path().join("skipped")).unwrap();
+        fs::write(temp_dir.path().join("skipped/check_hidden.py"), "def check_hidden():\n    pass\n").unwrap();
+
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.apply_config_file().unwrap();
+
+        let node_ids = collector.filtered_node_ids(&TestFilter::new(None, None));
+        assert_eq!(
+            node_ids,
+            vec!["check_things.py::check_a".to_string(), "check_things.py::CheckSuite::check_c".to_string()]
+        );
+        assert!(temp_dir.path().join(".cache/fastcollect.json").is_file());
+    }
+
+    #[test]
+    fn test_strict_config_rejects_unknown_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(&temp_dir, ".fastcollect.toml", "test_patterns = [\"check_*.py\"]\ntest_pattern = [\"typo\"]\n");
+
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.apply_config_file().unwrap();
+        assert_eq!(collector.test_patterns, vec!["check_*.py".to_string()]);
+
+        collector.strict_config = true;
+        let err = collector.apply_config_file().unwrap_err();
+        assert_eq!(err.to_string(), "unknown keys: test_pattern");
+    }
+
+    #[test]
+    fn test_missing_fastcollect_toml_is_noop() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.apply_config_file().unwrap();
+        assert_eq!(collector.test_patterns, vec!["test_*.py".to_string(), "*_test.py".to_string()]);
+        assert!(collector.cache_path.read().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_unknown_markers_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        let conftest = r#"
+def pytest_configure(config):
+    config.addinivalue_line("markers", "slow: marks tests as slow")
+"#;
+        create_test_file(&temp_dir, "conftest.py", conftest);
+        let content = r#"
+import pytest
+
+@pytest.mark.slow
+def test_slow():
+    pass
+
+@pytest.mark.slwo
+@pytest.mark.skip
+def test_typo():
+    pass
+"#;
+        create_test_file(&temp_dir, "test_markers.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        collector.scan_conftest();
+        assert!(collector.registered_markers.contains("slow"));
+        assert_eq!(
+            collector.unknown_markers(),
+            vec![("test_markers.py::test_typo".to_string(), "slwo".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_incremental_walk_relists_only_changed_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        for dir in ["a", "b", "c"] {
+            fs::create_dir(temp_dir.path().join(dir)).unwrap();
+            fs::write(temp_dir.path().join(dir).join("test_x.py"), "def test_x():\n    pass\n").unwrap();
+        }
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.set_incremental_walk(true);
+        let filter = TestFilter::new(None, None);
+
+        assert_eq!(collector.filtered_node_ids(&filter).len(), 3);
+        assert_eq!(collector.stats.dirs_listed.load(Ordering::Relaxed), 4);
+
+        collector.filtered_node_ids(&filter);
+        assert_eq!(collector.stats.dirs_listed.load(Ordering::Relaxed), 0);
+
+        fs::write(temp_dir.path().join("b/test_new.py"), "def test_new():\n    pass\n").unwrap();
+        let node_ids = collector.filtered_node_ids(&filter);
+        assert_eq!(collector.stats.dirs_listed.load(Ordering::Relaxed), 1);
+        assert!(node_ids.contains(&"b/test_new.py::test_new".to_string()));
+        assert_eq!(node_ids.len(), 4);
+    }
+
+    #[test]
+    fn test_incremental_walk_applies_file_ignores() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        create_test_file(&temp_dir, "conftest.py", "collect_ignore = [\"sub/test_legacy.py\"]\n");
+        for name in ["test_a.py", "test_skipped.py", "sub/test_b.py", "sub/test_legacy.py"] {
+            create_test_file(&temp_dir, name, "def test_x():\n    pass\n");
+        }
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.ignore_patterns.push("test_skip*".to_string());
+        collector.scan_conftest();
+
+        let mut walked = collector.find_test_files();
+        walked.sort();
+        collector.set_incremental_walk(true);
+        let incremental = collector.find_test_files();
+
+        assert_eq!(incremental, walked);
+        assert_eq!(incremental, vec![temp_dir.path().join("sub/test_b.py"), temp_dir.path().join("test_a.py")]);
+    }
+
+    #[test]
+    fn test_param_case_marks_filter_individual_cases() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+@pytest.mark.parametrize("value", [1, pytest.param(2, marks=pytest.mark.skip), 3])
+def test_value(value):
+    pass
+"#;
+        create_test_file(&temp_dir, "test_cases.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let not_skip = TestFilter::new(None, Some("not skip".to_string()));
+        assert_eq!(
+            collector.filtered_node_ids(&not_skip),
+            vec!["test_cases.py::test_value[1]".to_string(), "test_cases.py::test_value[3]".to_string()]
+        );
+
+        let skip = TestFilter::new(None, Some("skip".to_string()));
+        assert_eq!(collector.filtered_node_ids(&skip), vec!["test_cases.py::test_value[2]".to_string()]);
+    }
+
+    #[test]
+    fn test_estimated_runtime_sums_durations() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "def test_a():\n    pass\n\ndef test_b():\n    pass\n\ndef test_new():\n    pass\n";
+        create_test_file(&temp_dir, "test_timing.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let durations = HashMap::from([
+            ("test_timing.py::test_a".to_string(), 1.5),
+            ("test_timing.py::test_b".to_string(), 2.5),
+            ("test_timing.py::test_removed".to_string(), 8.0),
+        ]);
+        let filter = TestFilter::new(None, None);
+
+        assert_eq!(collector.estimated_runtime(&durations, &filter, Some(0.5)), 4.5);
+        // Unknown tests default to the mean known duration
+        assert_eq!(collector.estimated_runtime(&durations, &filter, None), 8.0);
+        let only_a = TestFilter::new(Some("test_a".to_string()), None);
+        assert_eq!(collector.estimated_runtime(&durations, &only_a, Some(0.5)), 1.5);
+    }
+
+    #[test]
+    fn test_async_methods_collected_in_classes() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+async def test_module_level():
+    pass
+
+class TestClient:
+    def test_sync(self):
+        pass
+
+    @pytest.mark.asyncio
+    async def test_async(self, client):
+        pass
+"#;
+        let file = create_test_file(&temp_dir, "test_async.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let items = collector.parse_test_file(&file).unwrap();
+        let names: Vec<_> = items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["test_module_level", "TestClient", "test_sync", "test_async"]);
+        assert!(items[0].is_async);
+        assert_eq!(items[0].item_type, TestItemType::Function);
+
+        assert!(!items[2].is_async);
+        let async_method = &items[3];
+        assert!(async_method.is_async);
+        assert_eq!(async_method.item_type, TestItemType::Method);
+        assert_eq!(async_method.class_name.as_deref(), Some("TestClient"));
+        assert_eq!(async_method.markers, vec!["asyncio".to_string()]);
+        assert_eq!(async_method.requested_fixtures, vec!["client".to_string()]);
+    }
+
+    #[test]
+    fn test_junit_skeleton_lists_each_test() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+def test_plain():
+    pass
+
+class TestAPI:
+    @pytest.mark.parametrize("op", ["a<b", "a&b"])
+    def test_compare(self, op):
+        pass
+"#;
+        fs::create_dir(temp_dir.path().join("tests")).unwrap();
+        fs::write(temp_dir.path().join("tests/test_api.py"), content).unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let xml = collector.junit_skeleton(&TestFilter::new(None, None));
+        assert!(xml.starts_with("<?xml"));
+        assert!(xml.contains("<testsuite name=\"pytest\" tests=\"3\">"));
+        assert_eq!(xml.matches("<testcase ").count(), 3);
+        assert!(xml.contains(r#"<testcase classname="tests.test_api" name="test_plain" file="tests/test_api.py" />"#));
+        assert!(xml.contains(r#"<testcase classname="tests.test_api.TestAPI" name="test_compare[a&lt;b]""#));
+        assert!(xml.contains(r#"name="test_compare[a&amp;b]""#));
+        assert!(xml.trim_end().ends_with("</testsuites>"));
+    }
+
+    #[test]
+    fn test_test_files_parsed_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = create_test_file(&temp_dir, "test_both_test.py", "def test_it():\n    pass\n");
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.test_patterns.push("test_*_test.py".to_string());
+
+        let node_ids = collector.filtered_node_ids(&TestFilter::new(None, None));
+        assert_eq!(node_ids, vec!["test_both_test.py::test_it".to_string()]);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 1);
+
+        // Different spellings of the same file collapse to the first one
+        let alias = temp_dir.path().join(".").join("test_both_test.py");
+        assert_eq!(dedupe_paths(vec![file.clone(), alias]), vec![file]);
+    }
+
+    #[test]
+    fn test_class_level_parametrize_multiplies_method_counts() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+@pytest.mark.parametrize("backend", ["sqlite", "postgres"])
+class TestStorage:
+    def test_plain(self, backend):
+        pass
+
+    @pytest.mark.parametrize("size", [1, 2, 3])
+    def test_sizes(self, backend, size):
+        pass
+"#;
+        let file = create_test_file(&temp_dir, "test_storage.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let items = collector.parse_test_file(&file).unwrap();
+        assert_eq!(items[1].name, "test_plain");
+        assert_eq!(items[1].parametrize_count, Some(2));
+        assert!(items[1].requested_fixtures.is_empty());
+        assert_eq!(items[2].name, "test_sizes");
+        assert_eq!(items[2].parametrize_count, Some(6));
+    }
+
+    #[test]
+    fn test_find_node_resolves_single_node_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+class TestAPI:
+    @pytest.mark.slow
+    def test_get(self):
+        pass
+
+    @pytest.mark.parametrize("code", [200, 404])
+    def test_status(self, code):
+        pass
+"#;
+        fs::create_dir(temp_dir.path().join("tests")).unwrap();
+        fs::write(temp_dir.path().join("tests/test_api.py"), content).unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let item = collector.find_node("tests/test_api.py::TestAPI::test_get").unwrap();
+        assert_eq!(item.line_number, 6);
+        assert_eq!(item.markers, vec!["slow".to_string()]);
+
+        let case = collector.find_node("tests/test_api.py::TestAPI::test_status[404]").unwrap();
+        assert_eq!(case.name, "test_status[404]");
+
+        assert!(collector.find_node("tests/test_api.py::TestAPI::test_missing").is_none());
+        assert!(collector.find_node("tests/test_gone.py::test_x").is_none());
+    }
+
+    #[test]
+    fn test_inherit_markers_toggle() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+@pytest.mark.integration
+class TestDatabase:
+    def test_query(self):
+        pass
+"#;
+        create_test_file(&temp_dir, "test_db.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let filter = TestFilter::new(None, Some("integration".to_string()));
+
+        assert_eq!(
+            collector.filtered_node_ids(&filter),
+            vec!["test_db.py::TestDatabase::test_query".to_string()]
+        );
+
+        collector.set_inherit_markers(false);
+        assert!(collector.filtered_node_ids(&filter).is_empty());
+        // Inherited markers are still reported on the item
+        let files = collector.collect_filtered(&TestFilter::new(None, None));
+        assert_eq!(files[0].test_items[1].inherited_markers, vec!["integration".to_string()]);
+    }
+
+    #[test]
+    fn test_dot_graph_has_containment_edges() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "def test_free():\n    pass\n\nclass TestAPI:\n    def test_get(self):\n        pass\n";
+        fs::create_dir(temp_dir.path().join("tests")).unwrap();
+        fs::write(temp_dir.path().join("tests/test_api.py"), content).unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let dot = collector.dot_graph();
+        assert!(dot.starts_with("digraph tests {"));
+        assert!(dot.trim_end().ends_with('}'));
+        assert!(dot.contains(r#""tests" -> "tests/test_api.py";"#));
+        assert!(dot.contains(r#""tests/test_api.py" -> "tests/test_api.py::test_free";"#));
+        assert!(dot.contains(r#""tests/test_api.py::TestAPI" -> "tests/test_api.py::TestAPI::test_get";"#));
+        assert!(dot.contains(r#""tests/test_api.py" [label="test_api.py", shape=note];"#));
+        assert!(dot.contains(r#""tests" [label="tests", shape=folder];"#));
+    }
+
+    #[test]
+    fn test_timeout_marker_value() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+@pytest.mark.timeout(30)
+def test_bounded():
+    pass
+
+@pytest.mark.timeout(timeout=2.5)
+def test_keyword():
+    pass
+
+def test_unbounded():
+    pass
+"#;
+        let file = create_test_file(&temp_dir, "test_timeout.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let items = collector.parse_test_file(&file).unwrap();
+        assert_eq!(items[0].timeout, Some(30.0));
+        assert_eq!(items[1].timeout, Some(2.5));
+        assert_eq!(items[2].timeout, None);
+
+        let objects = collector.item_objects(&TestFilter::new(None, None));
+        let timeouts: Vec<_> = objects.iter().map(CollectedItem::get_timeout).collect();
+        assert_eq!(timeouts, vec![Some(30.0), Some(2.5), None]);
+    }
+
+    #[test]
+    fn test_save_cache_merges_disjoint_collections() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_file = temp_dir.path().join("cache.json");
+        for dir in ["unit", "integration"] {
+            fs::create_dir(temp_dir.path().join(dir)).unwrap();
+            fs::write(temp_dir.path().join(dir).join("test_x.py"), "def test_x():\n    pass\n").unwrap();
+        }
+
+        for dir in ["unit", "integration"] {
+            let collector = FastCollector::new(temp_dir.path().join(dir).to_str().unwrap().to_string());
+            *collector.cache_path.write().unwrap() = Some(cache_file.clone());
+            collector.collect_filtered(&TestFilter::new(None, None));
+        }
+
+        let cache_data: CacheData = serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+        assert_eq!(cache_data.entries.len(), 2);
+        assert!(cache_data.entries.keys().any(|path| path.contains("unit")));
+        assert!(cache_data.entries.keys().any(|path| path.contains("integration")));
+    }
+
+    #[test]
+    fn test_crlf_line_numbers_match_editor_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "import pytest\r\n\r\ndef test_first():\r\n    pass\r\rdef test_second():\n    pass\r\n\r\nclass TestMixed:\r\n    def test_method(self):\r\n        pass\r\n";
+        let file = create_test_file(&temp_dir, "test_crlf.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let items = collector.parse_test_file(&file).unwrap();
+        let lines: Vec<_> = items.iter().map(|item| (item.name.as_str(), item.line_number, item.end_line)).collect();
+        assert_eq!(
+            lines,
+            vec![("test_first", 3, 4), ("test_second", 6, 7), ("TestMixed", 9, 11), ("test_method", 10, 11)]
+        );
+    }
+
+    #[test]
+    fn test_include_and_exclude_globs_scope_files() {
+        let temp_dir = TempDir::new().unwrap();
+        for dir in ["tests", "tests/slow", "tests/unit", "other"] {
+            fs::create_dir_all(temp_dir.path().join(dir)).unwrap();
+            fs::write(temp_dir.path().join(dir).join("test_x.py"), "def test_x():\n    pass\n").unwrap();
+        }
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let filter = TestFilter {
+            include_globs: vec!["tests/**".to_string()],
+            exclude_globs: vec!["tests/slow/**".to_string()],
+            ..TestFilter::new(None, None)
+        };
+
+        let mut node_ids = collector.filtered_node_ids(&filter);
+        node_ids.sort();
+        assert_eq!(
+            node_ids,
+            vec!["tests/test_x.py::test_x".to_string(), "tests/unit/test_x.py::test_x".to_string()]
+        );
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_path_globs_are_segment_aware() {
+        assert!(matches_path_glob("tests/test_x.py", "tests/*.py"));
+        assert!(!matches_path_glob("tests/unit/test_x.py", "tests/*.py"));
+        assert!(matches_path_glob("tests/unit/test_x.py", "tests/**/*.py"));
+        assert!(matches_path_glob("tests/test_x.py", "tests/**/*.py"));
+        assert!(matches_path_glob("tests/unit/deep/test_x.py", "tests/**"));
+        assert!(!matches_path_glob("other/test_x.py", "tests/**"));
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("tests/unit")).unwrap();
+        fs::write(temp_dir.path().join("tests/test_top.py"), "def test_top():\n    pass\n").unwrap();
+        fs::write(temp_dir.path().join("tests/unit/test_nested.py"), "def test_nested():\n    pass\n").unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let filter = TestFilter { include_globs: vec!["tests/*.py".to_string()], ..TestFilter::new(None, None) };
+        assert_eq!(collector.filtered_node_ids(&filter), vec!["tests/test_top.py::test_top"]);
+    }
+
+    #[test]
+    fn test_is_async_serialized_in_json() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "def test_sync():\n    pass\n\nasync def test_coroutine():\n    pass\n";
+        create_test_file(&temp_dir, "test_flags.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let metadata = collector.collect_filtered(&TestFilter::new(None, None));
+        let json: serde_json::Value = serde_json::to_value(&metadata).unwrap();
+        let items = json[0]["test_items"].as_array().unwrap();
+        assert_eq!(items[0]["name"], "test_sync");
+        assert_eq!(items[0]["is_async"], false);
+        assert_eq!(items[1]["name"], "test_coroutine");
+        assert_eq!(items[1]["is_async"], true);
+    }
+
+    #[test]
+    fn test_skip_reasons_captured_and_propagated() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import sys
+import pytest
+
+@pytest.mark.skip(reason="not ready")
+def test_pending():
+    pass
+
+@pytest.mark.skipif(sys.platform == "win32", reason="posix only")
+class TestPosix:
+    def test_fork(self):
+        pass
+
+    @pytest.mark.skip("broken upstream")
+    def test_own_reason(self):
+        pass
+
+def test_runs():
+    pass
+"#;
+        let file = create_test_file(&temp_dir, "test_skips.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let items = collector.parse_test_file(&file).unwrap();
+        let reasons: Vec<_> = items.iter().map(|item| (item.name.as_str(), item.skip_reason.as_deref())).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("test_pending", Some("not ready")),
+                ("TestPosix", Some("posix only")),
+                ("test_fork", Some("posix only")),
+                ("test_own_reason", Some("broken upstream")),
+                ("test_runs", None),
+            ]
+        );
+
+        let module_level = "import pytest\n\npytestmark = pytest.mark.skip(reason=\"legacy\")\n\ndef test_old():\n    pass\n";
+        let file = create_test_file(&temp_dir, "test_legacy.py", module_level);
+        let items = collector.parse_test_file(&file).unwrap();
+        assert_eq!(items[0].skip_reason.as_deref(), Some("legacy"));
+    }
+
+    #[test]
+    fn test_marker_combos_counts_buckets() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+@pytest.mark.slow
+@pytest.mark.integration
+def test_both():
+    pass
+
+@pytest.mark.slow
+class TestSlow:
+    @pytest.mark.integration
+    def test_inherited(self):
+        pass
+
+    def test_only_slow(self):
+        pass
+
+def test_plain():
+    pass
+"#;
+        create_test_file(&temp_dir, "test_combos.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let combos = collector.marker_combos();
+        assert_eq!(combos.get("integration,slow"), Some(&2));
+        assert_eq!(combos.get("slow"), Some(&1));
+        assert_eq!(combos.get(""), Some(&1));
+        assert_eq!(combos.len(), 3);
+    }
+
+    #[test]
+    fn test_duplicate_node_ids_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+def test_shadowed():
+    pass
+
+def test_shadowed():
+    pass
+
+@pytest.mark.parametrize("value", ["a", "b"], ids=["same", "same"])
+def test_ids(value):
+    pass
+
+def test_unique():
+    pass
+"#;
+        create_test_file(&temp_dir, "test_dupes.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        assert_eq!(
+            collector.duplicate_node_ids(),
+            vec!["test_dupes.py::test_ids[same]".to_string(), "test_dupes.py::test_shadowed".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_collect_source_without_filesystem() {
+        let temp_dir = TempDir::new().unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let metadata = collector.collect_source("def test_virtual():\n    pass\n", "pkg/test_virtual.py");
+        assert_eq!(metadata.path, "pkg/test_virtual.py");
+        assert_eq!(metadata.test_items.len(), 1);
+        assert_eq!(collector.node_id(&metadata.test_items[0]), "pkg/test_virtual.py::test_virtual");
+
+        collector.collect_source("def test_bad(:\n", "pkg/test_broken.py");
+        assert_eq!(collector.parse_errors.read().unwrap()[0].file_path, "pkg/test_broken.py");
+    }
+
+    #[test]
+    fn test_capture_signature() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+class TestViews:
+    def test_index(self, db, client, retries=3):
+        pass
+
+def test_flexible(request, *args, flag=False, **kwargs):
+    pass
+"#;
+        let file = create_test_file(&temp_dir, "test_sig.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        assert!(collector.parse_test_file(&file).unwrap()[1].signature.is_empty());
+
+        collector.set_capture_signature(true);
+        let items = collector.parse_test_file(&file).unwrap();
+        assert_eq!(items[1].signature, "test_index(self, db, client, retries)");
+        assert_eq!(items[2].signature, "test_flexible(request, *args, flag, **kwargs)");
+    }
+
+    #[test]
+    fn test_root_ignores_apply_within_their_subtree() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "def test_a():\n    pass\n";
+        fs::create_dir_all(temp_dir.path().join("api/fixtures")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("api/slow")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("web/fixtures")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("web/slow")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("other")).unwrap();
+        for dir in ["api/fixtures", "api/slow", "web/fixtures", "web/slow", "other"] {
+            create_test_file(&temp_dir, &format!("{}/test_x.py", dir), content);
+        }
+
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.add_root_with_ignores("api".to_string(), vec!["slow".to_string()]);
+        collector.add_root_with_ignores("web".to_string(), vec!["fix*".to_string()]);
+
+        let mut found: Vec<String> = collector
+            .find_test_files()
+            .iter()
+            .map(|path| collector.relative_path(path))
+            .collect();
+        found.sort();
+        assert_eq!(found, vec!["api/fixtures/test_x.py", "web/slow/test_x.py"]);
+
+        collector.incremental_walk = true;
+        let mut found: Vec<String> = collector
+            .find_test_files()
+            .iter()
+            .map(|path| collector.relative_path(path))
+            .collect();
+        found.sort();
+        assert_eq!(found, vec!["api/fixtures/test_x.py", "web/slow/test_x.py"]);
+    }
+
+    #[test]
+    fn test_new_markers_since_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_file = temp_dir.path().join("cache.json");
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        *collector.cache_path.write().unwrap() = Some(cache_file);
+        create_test_file(&temp_dir, "test_a.py", "import pytest\n\n@pytest.mark.slow\ndef test_a():\n    pass\n");
+        collector.collect_filtered(&TestFilter::new(None, None));
+
+        create_test_file(
+            &temp_dir,
+            "test_b.py",
+            "import pytest\n\n@pytest.mark.slow\n@pytest.mark.integraton\ndef test_b():\n    pass\n",
+        );
+        assert_eq!(collector.new_markers(), vec!["integraton"]);
+        // The run above updated the cache
+        assert!(collector.new_markers().is_empty());
+    }
+
+    #[test]
+    fn test_conditional_defs_skip_main_guard() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import sys
+
+if sys.platform == "linux":
+    def test_linux():
+        pass
+elif sys.platform == "darwin":
+    def test_darwin():
+        pass
+
+if __name__ == "__main__":
+    def test_script_only():
+        pass
+"#;
+        let file = create_test_file(&temp_dir, "test_cond.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        assert!(collector.parse_test_file(&file).unwrap().is_empty());
+
+        collector.set_collect_conditional_defs(true);
+        let names: Vec<String> = collector.parse_test_file(&file).unwrap().into_iter().map(|item| item.name).collect();
+        assert_eq!(names, vec!["test_linux", "test_darwin"]);
+    }
+
+    #[test]
+    fn test_blank_filter_expressions_match_everything() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+@pytest.mark.slow
+def test_marked():
+    pass
+
+class TestGroup:
+    def test_method(self):
+        pass
+"#;
+        let file = create_test_file(&temp_dir, "test_blank.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let items = collector.parse_test_file(&file).unwrap();
+
+        for expr in ["", "   "] {
+            let keyword = TestFilter::new(Some(expr.to_string()), None);
+            let marker = TestFilter::new(None, Some(expr.to_string()));
+            assert!(items.iter().all(|item| keyword.matches(item) && marker.matches(item)));
+        }
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use std::io::Write;
-    use tempfile::TempDir;
+    #[test]
+    fn test_collect_allowlist() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "def test_one():\n    pass\n\ndef test_two():\n    pass\n\ndef test_three():\n    pass\n";
+        create_test_file(&temp_dir, "test_keep.py", content);
+        create_test_file(&temp_dir, "test_other.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
 
-    /// Helper function to create a temporary test file
-    fn create_test_file(dir: &TempDir, name: &str, content: &str) -> PathBuf {
-        let file_path = dir.path().join(name);
-        let mut file = fs::File::create(&file_path).unwrap();
-        file.write_all(content.as_bytes()).unwrap();
-        file_path
+        let allow = HashMap::from([(
+            "test_keep.py".to_string(),
+            vec!["test_one".to_string(), "test_three".to_string()],
+        )]);
+        let files = collector.collect_allowlisted(&allow);
+        assert_eq!(files.len(), 1);
+        let names: Vec<_> = files[0].test_items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["test_one", "test_three"]);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 1);
     }
 
     #[test]
-    fn test_is_test_function_with_test_prefix() {
-        let collector = FastCollector::new("/tmp".to_string());
-        assert!(collector.is_test_function("test_foo"));
-        assert!(collector.is_test_function("test_bar_baz"));
-        assert!(collector.is_test_function("test"));
+    fn test_mark_namespace_alias() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+m = pytest.mark
+
+@m.slow
+def test_aliased():
+    pass
+
+@m.timeout(5)
+@pytest.mark.integration
+def test_mixed():
+    pass
+"#;
+        let file = create_test_file(&temp_dir, "test_alias.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let items = collector.parse_test_file(&file).unwrap();
+        assert_eq!(items[0].markers, vec!["slow"]);
+        assert_eq!(items[1].markers, vec!["timeout", "integration"]);
     }
 
     #[test]
-    fn test_is_test_function_without_test_prefix() {
-        let collector = FastCollector::new("/tmp".to_string());
-        assert!(!collector.is_test_function("foo_test"));
-        assert!(!collector.is_test_function("my_function"));
-        assert!(!collector.is_test_function("Test"));
+    fn test_parametrize_count_resolves_aliases_and_factories() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+m = pytest.mark
+
+def cases():
+    return pytest.mark.parametrize("x", load_cases())
+
+@m.parametrize("x", [1, 2, 3])
+def test_aliased(x):
+    pass
+
+@cases()
+def test_factory(x):
+    pass
+"#;
+        let file = create_test_file(&temp_dir, "test_param_alias.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.unknown_parametrize_count = Some(1);
+        let items = collector.parse_test_file(&file).unwrap();
+        assert_eq!(items[0].parametrize_count, Some(3));
+        // The factory's argvalues are not visible: counted as unknown
+        assert_eq!(items[1].parametrize_count, Some(1));
     }
 
     #[test]
-    fn test_is_test_class_with_test_prefix() {
-        let collector = FastCollector::new("/tmp".to_string());
-        assert!(collector.is_test_class("TestFoo"));
-        assert!(collector.is_test_class("TestBarBaz"));
-        assert!(collector.is_test_class("Test"));
+    fn test_result_cache_serves_repeated_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = create_test_file(&temp_dir, "test_rc.py", "def test_a():\n    pass\n");
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.set_result_cache(true);
+        let filter = TestFilter::new(Some("test_a".to_string()), None);
+
+        let first = collector.cached_node_ids(&filter);
+        assert_eq!(first, vec!["test_rc.py::test_a"]);
+        assert_eq!(collector.stats.result_cache_hits.load(Ordering::Relaxed), 0);
+
+        assert_eq!(collector.cached_node_ids(&filter), first);
+        assert_eq!(collector.stats.result_cache_hits.load(Ordering::Relaxed), 1);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 0);
+
+        // A modified file invalidates the result
+        fs::write(&file, "def test_a():\n    pass\n\ndef test_a_two():\n    pass\n").unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(later).unwrap();
+        assert_eq!(collector.cached_node_ids(&filter), vec!["test_rc.py::test_a", "test_rc.py::test_a_two"]);
+        assert_eq!(collector.stats.result_cache_hits.load(Ordering::Relaxed), 0);
     }
 
     #[test]
-    fn test_is_test_class_without_test_prefix() {
-        let collector = FastCollector::new("/tmp".to_string());
-        assert!(!collector.is_test_class("FooTest"));
-        assert!(!collector.is_test_class("MyClass"));
-        assert!(!collector.is_test_class("test_foo"));
+    fn test_trace_explains_filter_miss() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "import pytest\n\n@pytest.mark.slow\ndef test_login():\n    pass\n\ndef test_logout():\n    pass\n";
+        create_test_file(&temp_dir, "test_auth.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.collect_filtered(&TestFilter::new(Some("login".to_string()), None));
+        assert!(collector.get_trace().is_empty());
+
+        collector.set_trace(true);
+        collector.collect_filtered(&TestFilter::new(Some("login".to_string()), None));
+        assert_eq!(
+            collector.get_trace(),
+            vec![
+                "test_auth.py: discovered",
+                "test_auth.py: served from cache",
+                "test_auth.py::test_login: collected",
+                "test_auth.py::test_logout: filtered out, -k 'login' does not match",
+            ]
+        );
+
+        collector.collect_filtered(&TestFilter::new(None, Some("not slow".to_string())));
+        assert!(collector.get_trace().contains(&"test_auth.py::test_login: filtered out, -m 'not slow' does not match".to_string()));
     }
 
     #[test]
-    fn test_is_test_file_with_valid_patterns() {
-        let collector = FastCollector::new("/tmp".to_string());
-        let test_file = PathBuf::from("test_foo.py");
-        let test_file2 = PathBuf::from("bar_test.py");
+    fn test_methods_inherited_from_mixins() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+class ReadMixin:
+    def test_read(self):
+        pass
 
-        assert!(collector.is_test_file(&test_file));
-        assert!(collector.is_test_file(&test_file2));
+    def test_shared(self):
+        pass
+
+class WriteMixin:
+    def test_write(self):
+        pass
+
+    def test_shared(self):
+        pass
+
+class TestStorage(ReadMixin, WriteMixin):
+    def test_own(self):
+        pass
+"#;
+        let file = create_test_file(&temp_dir, "test_mixins.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let names: Vec<String> = collector
+            .parse_test_file(&file)
+            .unwrap()
+            .into_iter()
+            .filter(|item| item.class_name.as_deref() == Some("TestStorage"))
+            .map(|item| item.name)
+            .collect();
+        assert_eq!(names, vec!["test_own", "test_read", "test_shared", "test_write"]);
+
+        let shared: Vec<TestItem> = collector
+            .parse_test_file(&file)
+            .unwrap()
+            .into_iter()
+            .filter(|item| item.name == "test_shared")
+            .collect();
+        assert_eq!(shared.len(), 1);
+        assert_eq!(shared[0].line_number, 6);
     }
 
     #[test]
-    fn test_is_test_file_with_invalid_patterns() {
-        let collector = FastCollector::new("/tmp".to_string());
-        let non_test = PathBuf::from("foo.py");
-        let non_python = PathBuf::from("test_foo.txt");
-        let no_extension = PathBuf::from("test_foo");
+    fn test_write_sqlite() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
 
-        assert!(!collector.is_test_file(&non_test));
-        assert!(!collector.is_test_file(&non_python));
-        assert!(!collector.is_test_file(&no_extension));
+@pytest.mark.slow
+def test_a():
+    pass
+
+class TestB:
+    def test_b(self):
+        pass
+"#;
+        create_test_file(&temp_dir, "test_db.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let db_path = temp_dir.path().join("tests.db");
+        let files = collector.collect_filtered(&TestFilter::new(None, None));
+        let item_count: usize = files.iter().map(|file_meta| file_meta.test_items.len()).sum();
+
+        assert_eq!(collector.write_sqlite(&db_path, &files).unwrap(), item_count);
+        // Rewriting replaces the rows instead of appending
+        collector.write_sqlite(&db_path, &files).unwrap();
+
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        let rows: usize = conn.query_row("SELECT COUNT(*) FROM tests", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, item_count);
+        let markers: String = conn
+            .query_row("SELECT markers FROM tests WHERE node_id = 'test_db.py::test_a'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(markers, r#"["slow"]"#);
     }
 
     #[test]
-    fn test_should_ignore_common_directories() {
-        let collector = FastCollector::new("/tmp".to_string());
+    fn test_node_id_separator() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+class TestOuter:
+    class TestInner:
+        def test_deep(self):
+            pass
 
-        assert!(collector.should_ignore(&PathBuf::from(".git")));
-        assert!(collector.should_ignore(&PathBuf::from("__pycache__")));
-        assert!(collector.should_ignore(&PathBuf::from(".tox")));
-        assert!(collector.should_ignore(&PathBuf::from(".venv")));
-        assert!(collector.should_ignore(&PathBuf::from("venv")));
+def test_top():
+    pass
+"#;
+        create_test_file(&temp_dir, "test_sep.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.node_id_separator = ">>".to_string();
+
+        let mut node_ids = collector.filtered_node_ids(&TestFilter::new(None, None));
+        node_ids.sort();
+        assert_eq!(
+            node_ids,
+            vec!["test_sep.py>>TestOuter>>TestInner>>test_deep", "test_sep.py>>test_top"]
+        );
+        assert_eq!(collector.find_node("test_sep.py>>test_top").unwrap().name, "test_top");
     }
 
     #[test]
-    fn test_should_not_ignore_regular_directories() {
-        let collector = FastCollector::new("/tmp".to_string());
+    fn test_suite_digest() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = create_test_file(&temp_dir, "test_digest.py", "def test_a():\n    pass\n");
+        let filter = TestFilter::new(None, None);
+
+        let first = FastCollector::new(temp_dir.path().to_str().unwrap().to_string()).suite_digest(&filter, false);
+        let second = FastCollector::new(temp_dir.path().to_str().unwrap().to_string()).suite_digest(&filter, false);
+        assert!(first.starts_with("blake3:"));
+        assert_eq!(first, second);
+        let with_bodies = FastCollector::new(temp_dir.path().to_str().unwrap().to_string()).suite_digest(&filter, true);
+
+        // Editing a body only changes the digest that covers bodies
+        fs::write(&file, "def test_a():\n    assert True\n").unwrap();
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        assert_eq!(collector.suite_digest(&filter, false), first);
+        assert_ne!(collector.suite_digest(&filter, true), with_bodies);
+
+        create_test_file(&temp_dir, "test_more.py", "def test_b():\n    pass\n");
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        assert_ne!(collector.suite_digest(&filter, false), first);
+    }
+
+    #[test]
+    fn test_applicable_conftests() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("api/v2")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("web")).unwrap();
+        create_test_file(&temp_dir, "conftest.py", "");
+        create_test_file(&temp_dir, "api/conftest.py", "");
+        create_test_file(&temp_dir, "web/conftest.py", "");
+        create_test_file(&temp_dir, "api/v2/test_deep.py", "def test_deep():\n    pass\n");
+
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let files = collector.collect_filtered(&TestFilter::new(None, None));
+        assert!(files[0].applicable_conftests.is_empty());
+
+        collector.scan_conftest();
+        let files = collector.collect_filtered(&TestFilter::new(None, None));
+        let expected: Vec<String> = ["api/conftest.py", "conftest.py"]
+            .iter()
+            .map(|relative| temp_dir.path().join(relative).to_string_lossy().to_string())
+            .collect();
+        assert_eq!(files[0].applicable_conftests, expected);
+    }
+
+    #[test]
+    fn test_missing_required_markers() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+@pytest.mark.unit
+def test_unit():
+    pass
+
+@pytest.mark.slow
+def test_untagged():
+    pass
+
+@pytest.mark.e2e
+class TestFlows:
+    def test_checkout(self):
+        pass
+"#;
+        create_test_file(&temp_dir, "test_policy.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let required = vec!["unit".to_string(), "integration".to_string(), "e2e".to_string()];
+        assert_eq!(collector.missing_required_markers(&required), vec!["test_policy.py::test_untagged"]);
+    }
+
+    #[test]
+    fn test_pep695_type_parameters() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+type Pair[T] = tuple[T, T]
+
+def test_generic[T: int](value: T, db) -> T:
+    return value
+
+class TestBox[T]:
+    def test_method[U](self, item: U):
+        pass
+"#;
+        let file = create_test_file(&temp_dir, "test_generic.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.capture_signature = true;
+        let parsed = collector.parse_file(&file).unwrap();
+        assert_eq!(parsed.parse_error, None);
+
+        let names: Vec<_> = parsed.items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["test_generic", "TestBox", "test_method"]);
+        assert_eq!(parsed.items[0].requested_fixtures, vec!["value", "db"]);
+        assert_eq!(parsed.items[0].signature, "test_generic(value, db)");
+        assert_eq!(parsed.items[2].requested_fixtures, vec!["item"]);
+    }
+
+    #[test]
+    fn test_expand_parametrize() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+@pytest.mark.parametrize("x", [1, 2, 3])
+def test_values(x):
+    pass
+
+@pytest.mark.parametrize("a", [1, 2])
+@pytest.mark.parametrize("b", [3])
+def test_stacked(a, b):
+    pass
+
+def test_plain():
+    pass
+"#;
+        create_test_file(&temp_dir, "test_expand.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.set_expand_parametrize(true);
+
+        let files = collector.collect_filtered(&TestFilter::new(None, None));
+        let items = &files[0].test_items;
+        let ids: Vec<_> = items.iter().map(|item| item.parametrize_id.as_deref()).collect();
+        assert_eq!(ids, vec![Some("1"), Some("2"), Some("3"), None, None]);
+        // Stacked parametrize ids are composed by pytest at runtime, so the test is not expanded
+        assert!(items[..3].iter().all(|item| item.parametrize_count.is_none()));
+        assert!(items[3].parametrize_count.is_some());
+        assert_eq!(
+            collector.filtered_node_ids(&TestFilter::new(None, None)),
+            vec![
+                "test_expand.py::test_values[1]",
+                "test_expand.py::test_values[2]",
+                "test_expand.py::test_values[3]",
+                "test_expand.py::test_stacked",
+                "test_expand.py::test_plain",
+            ]
+        );
+    }
+
+    #[cfg(feature = "git")]
+    #[test]
+    fn test_collect_from_git_ref() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("tests")).unwrap();
+        create_test_file(&temp_dir, "tests/test_git.py", "def test_committed():\n    pass\n");
+        create_test_file(&temp_dir, "helpers.py", "def test_not_a_test_file():\n    pass\n");
+        let utf16: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain("def test_utf16():\n    pass\n".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        fs::write(temp_dir.path().join("tests/test_utf16.py"), utf16).unwrap();
+        fs::write(temp_dir.path().join("tests/test_latin1.py"), b"# -*- coding: latin-1 -*-\ndef test_caf\xe9():\n    pass\n").unwrap();
+        fs::write(temp_dir.path().join("tests/test_binary.py"), b"x = '\xe9'\n").unwrap();
+
+        let repo = git2::Repository::init(temp_dir.path()).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "Add tests", &tree, &[]).unwrap();
+
+        // The working copy no longer matches the commit
+        create_test_file(&temp_dir, "tests/test_git.py", "def test_uncommitted():\n    pass\n");
+
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.set_git_ref(Some("HEAD".to_string()));
+        let mut node_ids = collector.filtered_node_ids(&TestFilter::new(None, None));
+        node_ids.sort();
+        assert_eq!(
+            node_ids,
+            vec!["tests/test_git.py::test_committed", "tests/test_latin1.py::test_café", "tests/test_utf16.py::test_utf16"]
+        );
+        assert_eq!(collector.stats.files_collected.load(Ordering::Relaxed), 3);
+        assert_eq!(collector.stats.tests_collected.load(Ordering::Relaxed), 3);
+        assert_eq!(
+            *collector.warnings.read().unwrap(),
+            vec!["Skipping undecodable test file tests/test_binary.py".to_string()]
+        );
+
+        // Rooted below the repository, only that subtree is collected
+        let mut collector = FastCollector::new(temp_dir.path().join("tests").to_str().unwrap().to_string());
+        collector.set_git_ref(Some("HEAD".to_string()));
+        assert_eq!(collector.filtered_node_ids(&TestFilter::new(None, None)).len(), 3);
+
+        // Path globs are relative to the root, as in the filesystem walk
+        let root_relative = TestFilter { include_globs: vec!["test_g*.py".to_string()], ..TestFilter::new(None, None) };
+        assert_eq!(collector.filtered_node_ids(&root_relative), vec!["tests/test_git.py::test_committed"]);
+        let repo_relative = TestFilter { include_globs: vec!["tests/*.py".to_string()], ..TestFilter::new(None, None) };
+        assert!(collector.filtered_node_ids(&repo_relative).is_empty());
+
+        collector.set_git_ref(Some("no-such-ref".to_string()));
+        assert!(collector.filtered_node_ids(&TestFilter::new(None, None)).is_empty());
+        assert_eq!(collector.warnings.read().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_marker_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
+
+@pytest.mark.slow
+def test_a():
+    pass
+
+def test_b():
+    pass
+
+@pytest.mark.slow
+class TestC:
+    @pytest.mark.db
+    def test_c(self):
+        pass
+"#;
+        create_test_file(&temp_dir, "test_index.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let files = collector.collect_filtered(&TestFilter::new(None, None));
+        let index = collector.marker_index(&files);
+
+        assert_eq!(index.len(), 2);
+        assert_eq!(index["slow"], vec!["test_index.py::test_a", "test_index.py::TestC::test_c"]);
+        assert_eq!(index["db"], vec!["test_index.py::TestC::test_c"]);
+    }
+
+    #[test]
+    fn test_conftest_collect_ignore() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("pkg/old")).unwrap();
+        create_test_file(&temp_dir, "pkg/conftest.py", "collect_ignore = [\"test_legacy.py\", \"old\"]\n");
+        create_test_file(&temp_dir, "pkg/test_legacy.py", "def test_legacy():\n    pass\n");
+        create_test_file(&temp_dir, "pkg/old/test_older.py", "def test_older():\n    pass\n");
+        create_test_file(&temp_dir, "pkg/test_current.py", "def test_current():\n    pass\n");
+        // Entries are relative to their conftest
+        create_test_file(&temp_dir, "test_legacy.py", "def test_top():\n    pass\n");
+
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        assert_eq!(collector.filtered_node_ids(&TestFilter::new(None, None)).len(), 4);
+
+        collector.scan_conftest();
+        let mut node_ids = collector.filtered_node_ids(&TestFilter::new(None, None));
+        node_ids.sort();
+        assert_eq!(node_ids, vec!["pkg/test_current.py::test_current", "test_legacy.py::test_top"]);
+    }
+
+    #[test]
+    fn test_detect_syntax_features() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+def test_command():
+    match command():
+        case "go":
+            pass
+    if (n := 3) > 2:
+        print(f"{n}")
+"#;
+        let file = create_test_file(&temp_dir, "test_syntax.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        assert!(collector.parse_file(&file).unwrap().syntax_features.is_empty());
+
+        collector.set_detect_syntax_features(true);
+        let files = collector.collect_filtered(&TestFilter::new(None, None));
+        assert_eq!(files[0].syntax_features, vec!["fstring", "match", "walrus"]);
+    }
+
+    #[test]
+    fn test_capture_offsets() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "# héllo\nimport pytest\n\nasync def test_first():\n    pass\n\nclass TestSecond:\n    def test_method(self):\n        assert True\n";
+        let file = create_test_file(&temp_dir, "test_offsets.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        assert_eq!(collector.parse_test_file(&file).unwrap()[0].byte_start, None);
+
+        collector.set_capture_offsets(true);
+        let items = collector.parse_test_file(&file).unwrap();
+        let bytes = fs::read(&file).unwrap();
+        let text = |item: &TestItem| std::str::from_utf8(&bytes[item.byte_start.unwrap()..item.byte_end.unwrap()]).unwrap().to_string();
+        assert_eq!(text(&items[0]), "async def test_first():\n    pass");
+        assert!(text(&items[1]).starts_with("class TestSecond:"));
+        assert_eq!(text(&items[2]), "def test_method(self):\n        assert True");
+    }
+
+    #[test]
+    fn test_compact_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_file = temp_dir.path().join("cache.json");
+        let doomed = create_test_file(&temp_dir, "test_doomed.py", "def test_doomed():\n    pass\n");
+        create_test_file(&temp_dir, "test_kept.py", "def test_kept():\n    pass\n");
+
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        *collector.cache_path.write().unwrap() = Some(cache_file.clone());
+        collector.collect_filtered(&TestFilter::new(None, None));
+        fs::remove_file(&doomed).unwrap();
+        let before = fs::metadata(&cache_file).unwrap().len() as usize;
+
+        let saved = collector.compact_cache_file().unwrap();
+        let after = fs::metadata(&cache_file).unwrap().len() as usize;
+        assert!(saved > 0);
+        assert_eq!(before - after, saved);
+
+        let cache_data: CacheData = serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+        assert_eq!(cache_data.entries.len(), 1);
+        assert!(cache_data.entries.keys().all(|path| path.ends_with("test_kept.py")));
+
+        // Later saves do not resurrect the pruned entry
+        collector.save_cache().unwrap();
+        let cache_data: CacheData = serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+        assert_eq!(cache_data.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_node_id_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("unit")).unwrap();
+        create_test_file(&temp_dir, "unit/test_x.py", "def test_y():\n    pass\n");
+        let mut collector = FastCollector::new(temp_dir.path().join("unit").to_str().unwrap().to_string());
+        collector.set_node_id_prefix("tests/unit/".to_string());
+
+        assert_eq!(
+            collector.filtered_node_ids(&TestFilter::new(None, None)),
+            vec!["tests/unit/test_x.py::test_y"]
+        );
+        assert_eq!(collector.find_node("tests/unit/test_x.py::test_y").unwrap().name, "test_y");
+        assert!(collector.find_node("test_x.py::test_y").is_none());
+    }
+
+    #[test]
+    fn test_unused_markers() {
+        let temp_dir = TempDir::new().unwrap();
+        let conftest = r#"
+def pytest_configure(config):
+    config.addinivalue_line("markers", "slow: marks tests as slow")
+    config.addinivalue_line("markers", "legacy: no longer used")
+    config.addinivalue_line("markers", "flaky: only on a case")
+"#;
+        create_test_file(&temp_dir, "conftest.py", conftest);
+        let content = r#"
+import pytest
+
+@pytest.mark.slow
+def test_slow():
+    pass
+
+@pytest.mark.parametrize("x", [1, pytest.param(2, marks=pytest.mark.flaky)])
+def test_cases(x):
+    pass
+"#;
+        create_test_file(&temp_dir, "test_markers.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.scan_conftest();
+        assert_eq!(collector.unused_markers(), vec!["legacy"]);
+    }
+
+    #[test]
+    fn test_inheritance_cycles_and_depth_bound() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+class TestA(TestB):
+    def test_a(self):
+        pass
+
+class TestB(TestA):
+    def test_b(self):
+        pass
+
+class Level2:
+    def test_level2(self):
+        pass
+
+class Level1(Level2):
+    def test_level1(self):
+        pass
+
+class TestDeep(Level1):
+    pass
+"#;
+        let file = create_test_file(&temp_dir, "test_cycle.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let parsed = collector.parse_file(&file).unwrap();
+        let of_class = |class: &str| -> Vec<String> {
+            parsed
+                .items
+                .iter()
+                .filter(|item| item.class_name.as_deref() == Some(class))
+                .map(|item| item.name.clone())
+                .collect()
+        };
+        assert_eq!(of_class("TestA"), vec!["test_a", "test_b"]);
+        assert_eq!(of_class("TestB"), vec!["test_b", "test_a"]);
+        assert_eq!(of_class("TestDeep"), vec!["test_level1", "test_level2"]);
+        assert_eq!(parsed.warnings.len(), 2);
+        assert!(parsed.warnings.iter().all(|warning| warning.contains("cyclic inheritance")));
+
+        collector.set_max_inheritance_depth(1);
+        let parsed = collector.parse_file(&file).unwrap();
+        let deep: Vec<_> = parsed.items.iter().filter(|item| item.class_name.as_deref() == Some("TestDeep")).collect();
+        assert_eq!(deep.len(), 1);
+        assert!(parsed.warnings.iter().any(|warning| warning.contains("deeper than 1 levels")));
+    }
+
+    #[test]
+    fn test_inheritance_warnings_survive_cache_hits() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "class TestA(TestB):\n    def test_a(self):\n        pass\n\nclass TestB(TestA):\n    def test_b(self):\n        pass\n";
+        create_test_file(&temp_dir, "test_cycle.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        collector.collect_filtered(&TestFilter::new(None, None));
+        let first = collector.warnings.read().unwrap().clone();
+        assert_eq!(first.len(), 2);
 
-        assert!(!collector.should_ignore(&PathBuf::from("tests")));
-        assert!(!collector.should_ignore(&PathBuf::from("src")));
-        assert!(!collector.should_ignore(&PathBuf::from("my_module")));
+        collector.collect_filtered(&TestFilter::new(None, None));
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 1);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 0);
+        assert_eq!(*collector.warnings.read().unwrap(), first);
     }
 
     #[test]
-    fn test_matches_wildcard_exact_match() {
-        let collector = FastCollector::new("/tmp".to_string());
+    fn test_collect_directory_non_recursive() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("api/nested")).unwrap();
+        create_test_file(&temp_dir, "test_root.py", "def test_root():\n    pass\n");
+        create_test_file(&temp_dir, "api/test_api.py", "def test_api():\n    pass\n");
+        create_test_file(&temp_dir, "api/helpers.py", "def test_helper():\n    pass\n");
+        create_test_file(&temp_dir, "api/nested/test_nested.py", "def test_nested():\n    pass\n");
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
 
-        assert!(collector.matches_wildcard("test.py", "test.py"));
-        assert!(!collector.matches_wildcard("test.py", "other.py"));
+        let names = |files: Vec<FileMetadata>| -> Vec<String> {
+            files.into_iter().flat_map(|file_meta| file_meta.test_items).map(|item| item.name).collect()
+        };
+        let api = temp_dir.path().join("api");
+        assert_eq!(names(collector.collect_directory(&api, false)), vec!["test_api"]);
+        assert_eq!(names(collector.collect_directory(&api, true)), vec!["test_nested", "test_api"]);
     }
 
     #[test]
-    fn test_matches_wildcard_prefix() {
-        let collector = FastCollector::new("/tmp".to_string());
+    fn test_type_counts() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
 
-        assert!(collector.matches_wildcard("test_foo.py", "test_*.py"));
-        assert!(collector.matches_wildcard("test_bar_baz.py", "test_*.py"));
-        assert!(!collector.matches_wildcard("foo_test.py", "test_*.py"));
+def test_one():
+    pass
+
+@pytest.mark.slow
+def test_two():
+    pass
+
+class TestA:
+    def test_a1(self):
+        pass
+
+    def test_a2(self):
+        pass
+
+class TestB:
+    class TestInner:
+        @pytest.mark.slow
+        def test_inner(self):
+            pass
+"#;
+        create_test_file(&temp_dir, "test_types.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        assert_eq!(collector.type_counts(&TestFilter::new(None, None)), (2, 3, 3));
+        assert_eq!(collector.type_counts(&TestFilter::new(None, Some("slow".to_string()))).0, 1);
     }
 
     #[test]
-    fn test_matches_wildcard_suffix() {
-        let collector = FastCollector::new("/tmp".to_string());
+    fn test_tap_plan() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = r#"
+import pytest
 
-        assert!(collector.matches_wildcard("foo_test.py", "*_test.py"));
-        assert!(collector.matches_wildcard("bar_baz_test.py", "*_test.py"));
-        assert!(!collector.matches_wildcard("test_foo.py", "*_test.py"));
+def test_one():
+    pass
+
+class TestGroup:
+    @pytest.mark.parametrize("x", [1, 2])
+    def test_case(self, x):
+        pass
+"#;
+        create_test_file(&temp_dir, "test_tap.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        assert_eq!(
+            collector.tap_plan(&TestFilter::new(None, None)),
+            "1..3\n\
+             # test: test_tap.py::test_one\n\
+             # test: test_tap.py::TestGroup::test_case[1]\n\
+             # test: test_tap.py::TestGroup::test_case[2]\n"
+        );
+        assert_eq!(collector.tap_plan(&TestFilter::new(Some("nothing".to_string()), None)), "1..0\n");
     }
 
     #[test]
-    fn test_matches_wildcard_middle() {
-        let collector = FastCollector::new("/tmp".to_string());
-
-        assert!(collector.matches_wildcard("test_foo_bar.py", "test_*_bar.py"));
-        assert!(!collector.matches_wildcard("test_foo.py", "test_*_bar.py"));
+    fn test_collect_changed_skips_untouched_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = create_test_file(&temp_dir, "test_watch.py", "def test_watch():\n    pass\n");
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.collect_filtered(&TestFilter::new(None, None));
+
+        // Reported as changed, but neither mtime nor content moved
+        collector.stats.reset();
+        let files = collector.collect_changed_files(std::slice::from_ref(&file), 0.0);
+        assert_eq!(files[0].test_items[0].name, "test_watch");
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 1);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 0);
+
+        // Without content hashes a touch cannot be told apart from an edit
+        let touched = SystemTime::now() + std::time::Duration::from_secs(1);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(touched).unwrap();
+        collector.stats.reset();
+        collector.collect_changed_files(std::slice::from_ref(&file), 5.0);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 1);
+
+        // With them, a touch within the interval is coalesced
+        collector.hash_algorithm = Some(HashAlgorithm::Blake3);
+        collector.collect_changed_files(std::slice::from_ref(&file), 5.0);
+        let touched = SystemTime::now() + std::time::Duration::from_secs(2);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(touched).unwrap();
+        collector.stats.reset();
+        collector.collect_changed_files(std::slice::from_ref(&file), 5.0);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 0);
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 1);
+
+        fs::write(&file, "def test_watch():\n    pass\n\ndef test_new():\n    pass\n").unwrap();
+        let edited = SystemTime::now() + std::time::Duration::from_secs(30);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(edited).unwrap();
+        collector.stats.reset();
+        let files = collector.collect_changed_files(&[file, temp_dir.path().join("test_gone.py")], 5.0);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 1);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].test_items.len(), 2);
     }
 
     #[test]
-    fn test_parse_simple_test_function() {
+    fn test_collect_changed_reparses_backward_mtime() {
         let temp_dir = TempDir::new().unwrap();
+        let file = create_test_file(&temp_dir, "test_watch.py", "def test_watch():\n    pass\n");
         let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.collect_filtered(&TestFilter::new(None, None));
+
+        // Replaced by an older version, e.g. by a checkout
+        fs::write(&file, "def test_older():\n    pass\n").unwrap();
+        let older = SystemTime::now() - std::time::Duration::from_secs(3600);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(older).unwrap();
+        collector.stats.reset();
+        let files = collector.collect_changed_files(std::slice::from_ref(&file), 5.0);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 1);
+        assert_eq!(files[0].test_items[0].name, "test_older");
+    }
+
+    #[test]
+    fn test_collect_changed_reparses_edits_within_interval() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = create_test_file(&temp_dir, "test_watch.py", "def test_watch():\n    pass\n");
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.collect_filtered(&TestFilter::new(None, None));
+
+        // A rapid second save that adds a test, well within the interval
+        fs::write(&file, "def test_watch():\n    pass\n\ndef test_added():\n    pass\n").unwrap();
+        let saved = SystemTime::now() + std::time::Duration::from_secs(1);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(saved).unwrap();
+        let files = collector.collect_changed_files(std::slice::from_ref(&file), 5.0);
+        assert_eq!(files[0].test_items.len(), 2);
+
+        // Content hashes tell the edit apart from a touch as well
+        collector.hash_algorithm = Some(HashAlgorithm::Blake3);
+        collector.collect_changed_files(std::slice::from_ref(&file), 5.0);
+        fs::write(&file, "def test_renamed():\n    pass\n").unwrap();
+        let saved = SystemTime::now() + std::time::Duration::from_secs(2);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(saved).unwrap();
+        let files = collector.collect_changed_files(std::slice::from_ref(&file), 5.0);
+        assert_eq!(files[0].test_items[0].name, "test_renamed");
+    }
 
+    #[test]
+    fn test_module_markers() {
+        let temp_dir = TempDir::new().unwrap();
         let content = r#"
-def test_simple():
-    assert True
+import pytest
+
+pytestmark = [pytest.mark.integration, pytest.mark.timeout(30)]
+
+@pytest.mark.slow
+def test_a():
+    pass
 "#;
-        let test_file = create_test_file(&temp_dir, "test_simple.py", content);
+        create_test_file(&temp_dir, "test_module_marks.py", content);
+        create_test_file(&temp_dir, "test_plain.py", "def test_b():\n    pass\n");
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let mut files = collector.collect_filtered(&TestFilter::new(None, None));
+        files.sort_by(|a, b| a.path.cmp(&b.path));
 
-        let items = collector.parse_test_file(&test_file).unwrap();
-        assert_eq!(items.len(), 1);
-        assert_eq!(items[0].name, "test_simple");
-        assert_eq!(items[0].class_name, None);
+        assert_eq!(files[0].module_markers, vec!["integration", "timeout"]);
+        assert!(files[1].module_markers.is_empty());
     }
 
     #[test]
-    fn test_parse_test_class() {
+    fn test_tests_importing_module() {
         let temp_dir = TempDir::new().unwrap();
+        create_test_file(&temp_dir, "test_auth.py", "import mypkg.auth.tokens\n\ndef test_login():\n    pass\n");
+        create_test_file(&temp_dir, "test_from.py", "from mypkg import auth\n\ndef test_logout():\n    pass\n");
+        create_test_file(&temp_dir, "test_other.py", "import mypkg.authz\nfrom .auth import helper\n\ndef test_other():\n    pass\n");
         let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
 
+        let mut node_ids = collector.node_ids_importing("mypkg.auth");
+        node_ids.sort();
+        assert_eq!(node_ids, vec!["test_auth.py::test_login", "test_from.py::test_logout"]);
+        assert!(collector.node_ids_importing("requests").is_empty());
+    }
+
+    #[test]
+    fn test_method_prefixes_apply_to_class_methods_only() {
+        let temp_dir = TempDir::new().unwrap();
         let content = r#"
-class TestFoo:
-    def test_method(self):
+def test_free():
+    pass
+
+def should_not_collect():
+    pass
+
+class TestLogin:
+    def should_login(self):
+        pass
+
+    def test_old_style(self):
         pass
 "#;
-        let test_file = create_test_file(&temp_dir, "test_class.py", content);
+        create_test_file(&temp_dir, "test_bdd.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.method_prefixes = Some(vec!["should_".to_string(), "it_".to_string()]);
 
-        let items = collector.parse_test_file(&test_file).unwrap();
-        // Should have both the class and the method
-        assert!(items.len() >= 1);
+        let node_ids = collector.filtered_node_ids(&TestFilter::new(None, None));
+        assert_eq!(node_ids, vec!["test_bdd.py::test_free", "test_bdd.py::TestLogin::should_login"]);
+    }
 
-        // Find the class item
-        let class_item = items.iter().find(|i| i.name == "TestFoo");
-        assert!(class_item.is_some());
+    #[test]
+    fn test_empty_method_prefixes_have_own_options_key() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(&temp_dir, "test_cls.py", "class TestA:\n    def test_a(self):\n        pass\n");
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let default_key = collector.parse_options_key();
+        assert_eq!(collector.filtered_node_ids(&TestFilter::new(None, None)), vec!["test_cls.py::TestA::test_a"]);
+
+        collector.set_method_prefixes(Vec::new());
+        assert_ne!(collector.parse_options_key(), default_key);
+        assert!(collector.filtered_node_ids(&TestFilter::new(None, None)).is_empty());
+    }
 
-        // Find the method item
-        let method_item = items.iter().find(|i| i.name == "test_method");
-        assert!(method_item.is_some());
-        assert_eq!(method_item.unwrap().class_name, Some("TestFoo".to_string()));
+    #[test]
+    fn test_collect_streaming_reports_parse_errors() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(&temp_dir, "test_ok.py", "def test_one():\n    pass\n");
+        let broken = create_test_file(&temp_dir, "test_broken.py", "def test_broken(:\n    pass\n");
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        collector.collect_streaming(
+            &TestFilter::new(None, None),
+            |file_meta| files.push(file_meta.path),
+            |error| errors.push(error),
+        );
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].file_path, broken.to_string_lossy());
+        assert_eq!(errors[0].line, 1);
     }
 
     #[test]
-    fn test_parse_with_markers() {
+    fn test_collect_with_mtimes_uses_supplied_mtimes() {
         let temp_dir = TempDir::new().unwrap();
+        create_test_file(&temp_dir, "test_a.py", "def test_a():\n    pass\n");
+        create_test_file(&temp_dir, "test_b.py", "def test_b():\n    pass\n");
         let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.collect_filtered(&TestFilter::new(None, None));
+
+        let files: Vec<(PathBuf, f64)> =
+            collector.cache.read().unwrap().iter().map(|(path, entry)| (PathBuf::from(path), entry.mtime)).collect();
+        collector.begin_collection();
+        let collected = collector.collect_with_mtimes(&files, &TestFilter::new(None, None));
+
+        assert_eq!(collected.len(), 2);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 0);
+        assert_eq!(collector.stats.cache_hits.load(Ordering::Relaxed), 2);
+    }
 
+    #[test]
+    fn test_unknown_parametrize_behavior() {
+        let temp_dir = TempDir::new().unwrap();
         let content = r#"
 import pytest
 
-@pytest.mark.slow
-def test_slow():
+CASES = load_cases()
+
+@pytest.mark.parametrize("case", CASES)
+def test_dynamic(case):
     pass
 
-@pytest.mark.smoke
-@pytest.mark.regression
-def test_combined():
+@pytest.mark.parametrize("x", [1, 2])
+def test_literal(x):
     pass
 "#;
-        let test_file = create_test_file(&temp_dir, "test_markers.py", content);
-
-        let items = collector.parse_test_file(&test_file).unwrap();
+        create_test_file(&temp_dir, "test_dynamic.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let counts = |collector: &FastCollector| -> Vec<Option<usize>> {
+            collector.collect_filtered(&TestFilter::new(None, None))[0]
+                .test_items
+                .iter()
+                .map(|item| item.parametrize_count)
+                .collect()
+        };
 
-        // Find slow test
-        let slow_test = items.iter().find(|i| i.name == "test_slow").unwrap();
-        assert!(slow_test.markers.contains(&"slow".to_string()));
+        assert_eq!(counts(&collector), vec![None, Some(2)]);
+        for (behavior, expected) in [("one", Some(1)), ("5", Some(5)), ("none", None)] {
+            collector.unknown_parametrize_count = parse_unknown_parametrize_behavior(behavior).unwrap();
+            collector.invalidate_parsed_items();
+            assert_eq!(counts(&collector), vec![expected, Some(2)]);
+        }
 
-        // Find combined test
-        let combined_test = items.iter().find(|i| i.name == "test_combined").unwrap();
-        assert!(combined_test.markers.contains(&"smoke".to_string()));
-        assert!(combined_test.markers.contains(&"regression".to_string()));
+        let error = parse_unknown_parametrize_behavior("many").unwrap_err();
+        assert!(error.contains("'many'"));
+        assert!(parse_unknown_parametrize_behavior("-1").is_err());
     }
 
     #[test]
-    fn test_parse_empty_file() {
+    fn test_tests_shadowing_conftest_fixtures_warn() {
         let temp_dir = TempDir::new().unwrap();
-        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let conftest = r#"
+import pytest
 
-        let content = "";
-        let test_file = create_test_file(&temp_dir, "test_empty.py", content);
+@pytest.fixture
+def test_data():
+    return {}
 
-        let items = collector.parse_test_file(&test_file).unwrap();
-        assert_eq!(items.len(), 0);
+@pytest.fixture(name="test_user")
+def user_fixture():
+    return "alice"
+"#;
+        create_test_file(&temp_dir, "conftest.py", conftest);
+        create_test_file(&temp_dir, "test_things.py", "def test_data():\n    pass\n\ndef test_other():\n    pass\n");
+        create_test_file(&temp_dir, "test_users.py", "class TestUsers:\n    def test_user(self):\n        pass\n");
+
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.scan_conftest();
+        collector.begin_collection();
+        collector.collect_filtered(&TestFilter::new(None, None));
+
+        let mut warnings = collector.warnings.read().unwrap().clone();
+        warnings.sort();
+        assert_eq!(
+            warnings,
+            vec![
+                "test_things.py::test_data: test name shadows the conftest fixture 'test_data'",
+                "test_users.py::TestUsers::test_user: test name shadows the conftest fixture 'test_user'",
+            ]
+        );
     }
 
     #[test]
-    fn test_parse_non_test_functions() {
+    fn test_leaf_only_emits_runnable_items() {
         let temp_dir = TempDir::new().unwrap();
-        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
-
         let content = r#"
-def helper_function():
-    pass
+import pytest
 
-def another_helper():
+def test_plain():
     pass
+
+class TestSuite:
+    @pytest.mark.parametrize("x", [1, 2])
+    def test_case(self, x):
+        pass
 "#;
-        let test_file = create_test_file(&temp_dir, "test_helpers.py", content);
+        create_test_file(&temp_dir, "test_leaves.py", content);
+        create_test_file(&temp_dir, "test_helpers.py", "def helper():\n    pass\n");
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.leaf_only = true;
+
+        let files = collector.collect_filtered(&TestFilter::new(None, None));
+        assert_eq!(files.len(), 1);
+        let node_ids: Vec<String> = files[0].test_items.iter().map(|item| collector.node_id(item)).collect();
+        assert_eq!(
+            node_ids,
+            vec![
+                "test_leaves.py::test_plain",
+                "test_leaves.py::TestSuite::test_case[1]",
+                "test_leaves.py::TestSuite::test_case[2]",
+            ]
+        );
+    }
 
-        let items = collector.parse_test_file(&test_file).unwrap();
-        assert_eq!(items.len(), 0);
+    #[test]
+    fn test_shuffle_seed_is_deterministic() {
+        let temp_dir = TempDir::new().unwrap();
+        let content: String = (0..20).map(|i| format!("def test_{i:02}():\n    pass\n\n")).collect();
+        create_test_file(&temp_dir, "test_many.py", &content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let filter = TestFilter::new(None, None);
+        let sorted = collector.filtered_node_ids(&filter);
+
+        collector.shuffle_seed = Some(42);
+        let first = collector.filtered_node_ids(&filter);
+        assert_eq!(collector.filtered_node_ids(&filter), first);
+        assert_ne!(first, sorted);
+        let mut reordered = first.clone();
+        reordered.sort();
+        assert_eq!(reordered, sorted);
+
+        collector.shuffle_seed = Some(7);
+        assert_ne!(collector.filtered_node_ids(&filter), first);
     }
 
     #[test]
-    fn test_parse_invalid_python() {
+    fn test_test_directories_are_distinct() {
         let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("tests/unit")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("tests/integration")).unwrap();
+        create_test_file(&temp_dir, "tests/unit/test_a.py", "def test_a():\n    pass\n");
+        create_test_file(&temp_dir, "tests/unit/test_b.py", "def test_b():\n    pass\n");
+        create_test_file(&temp_dir, "tests/integration/test_c.py", "def test_c():\n    pass\n");
+        create_test_file(&temp_dir, "test_root.py", "def test_root():\n    pass\n");
         let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
 
-        let content = "this is not valid python syntax !!!";
-        let test_file = create_test_file(&temp_dir, "test_invalid.py", content);
+        assert_eq!(collector.test_file_directories(), vec![".", "tests/integration", "tests/unit"]);
+    }
 
-        let items = collector.parse_test_file(&test_file).unwrap();
-        // Should return empty vec on parse error, not crash
-        assert_eq!(items.len(), 0);
+    #[test]
+    fn test_order_key_ignores_line_shifts() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "class TestAPI:\n    def test_get(self):\n        pass\n";
+        let file = create_test_file(&temp_dir, "test_order.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.order_keys = true;
+        let before = collector.parse_test_file(&file).unwrap();
+
+        fs::write(&file, format!("import os\n\n\ndef helper():\n    pass\n\n{}", content)).unwrap();
+        let after = collector.parse_test_file(&file).unwrap();
+
+        assert_ne!(before[1].line_number, after[1].line_number);
+        assert_eq!(before[1].order_key.as_deref(), Some("test_order.py::TestAPI.test_get"));
+        assert_eq!(after[1].order_key, before[1].order_key);
     }
 
     #[test]
-    fn test_find_test_files_in_directory() {
+    fn test_collect_by_node_id_prefix() {
         let temp_dir = TempDir::new().unwrap();
+        let content = "class TestAuth:\n    def test_login(self):\n        pass\n\n    def test_logout(self):\n        pass\n\nclass TestUsers:\n    def test_list(self):\n        pass\n\ndef test_free():\n    pass\n";
+        create_test_file(&temp_dir, "test_auth.py", content);
+        create_test_file(&temp_dir, "test_other.py", "class TestAuth:\n    def test_elsewhere(self):\n        pass\n");
         let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
 
-        // Create test files
-        create_test_file(&temp_dir, "test_one.py", "def test_one(): pass");
-        create_test_file(&temp_dir, "test_two.py", "def test_two(): pass");
-        create_test_file(&temp_dir, "helper.py", "def foo(): pass");
+        let files = collector.collect_by_prefix(&["test_auth.py::TestAuth".to_string()]);
+        assert_eq!(files.len(), 1);
+        let methods: Vec<&str> = files[0]
+            .test_items
+            .iter()
+            .filter(|item| matches!(item.item_type, TestItemType::Method))
+            .map(|item| item.name.as_str())
+            .collect();
+        assert_eq!(methods, vec!["test_login", "test_logout"]);
+    }
 
-        let test_files = collector.find_test_files();
+    #[test]
+    fn test_collect_by_prefix_stops_at_node_boundaries() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "class TestAuth:\n    def test_login(self):\n        pass\n\nclass TestAuthz:\n    def test_grant(self):\n        pass\n";
+        create_test_file(&temp_dir, "test_auth.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
 
-        assert_eq!(test_files.len(), 2);
-        assert!(test_files.iter().any(|p| p.file_name().unwrap() == "test_one.py"));
-        assert!(test_files.iter().any(|p| p.file_name().unwrap() == "test_two.py"));
+        let names = |prefix: &str| -> Vec<String> {
+            collector
+                .collect_by_prefix(&[prefix.to_string()])
+                .iter()
+                .flat_map(|file_meta| &file_meta.test_items)
+                .map(|item| item.name.clone())
+                .collect()
+        };
+        assert_eq!(names("test_auth.py::TestAuth"), vec!["TestAuth", "test_login"]);
+        assert_eq!(names("test_auth.py::TestAuth::test_log"), Vec::<String>::new());
+        assert_eq!(names("test_auth.py::TestAuthz"), vec!["TestAuthz", "test_grant"]);
+        assert_eq!(names("test_auth.py").len(), 4);
     }
 
     #[test]
-    fn test_find_test_files_ignores_directories() {
+    fn test_prometheus_metrics() {
         let temp_dir = TempDir::new().unwrap();
+        create_test_file(&temp_dir, "test_a.py", "def test_a():\n    pass\n\ndef test_b():\n    pass\n");
+        create_test_file(&temp_dir, "test_c.py", "class TestC:\n    def test_c(self):\n        pass\n");
+        create_test_file(&temp_dir, "test_broken.py", "def test_broken(:\n");
         let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.collect_filtered(&TestFilter::new(None, None));
+
+        let metrics = collector.prometheus_metrics();
+        let lines: Vec<&str> = metrics.lines().filter(|line| !line.starts_with('#')).collect();
+        assert_eq!(&lines[..4], [
+            "fastcollect_files 2",
+            "fastcollect_tests 3",
+            "fastcollect_cache_hits 0",
+            "fastcollect_parse_errors 1",
+        ]);
+        assert!(metrics.contains("# TYPE fastcollect_collection_duration_seconds gauge"));
+        let duration: f64 = lines[4].strip_prefix("fastcollect_collection_duration_seconds ").unwrap().parse().unwrap();
+        assert!(duration > 0.0);
+    }
 
-        // Create test file
-        create_test_file(&temp_dir, "test_one.py", "def test_one(): pass");
+    #[test]
+    fn test_find_suspicious_files() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(&temp_dir, "tests_login.py", "def test_foo():\n    pass\n");
+        create_test_file(&temp_dir, "helpers.py", "def build_user():\n    pass\n");
+        create_test_file(&temp_dir, "test_real.py", "def test_real():\n    pass\n");
+        create_test_file(&temp_dir, "conftest.py", "def test_like_hook():\n    pass\n");
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
 
-        // Create ignored directory with test file
-        let ignored_dir = temp_dir.path().join("__pycache__");
-        fs::create_dir(&ignored_dir).unwrap();
-        let mut ignored_file = fs::File::create(ignored_dir.join("test_cached.py")).unwrap();
-        ignored_file.write_all(b"def test_cached(): pass").unwrap();
+        assert_eq!(collector.suspicious_files(), vec!["tests_login.py"]);
+    }
 
-        let test_files = collector.find_test_files();
+    #[test]
+    fn test_index_queries_run_in_memory() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "import pytest\n\n@pytest.mark.slow\ndef test_slow_login():\n    pass\n\ndef test_fast_login():\n    pass\n\ndef test_logout():\n    pass\n";
+        let file = create_test_file(&temp_dir, "test_auth.py", content);
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        assert_eq!(collector.rebuild_index(), 3);
 
-        // Should only find test_one.py, not test_cached.py
-        assert_eq!(test_files.len(), 1);
-        assert_eq!(test_files[0].file_name().unwrap(), "test_one.py");
+        // Queries are answered from the index even once the file is gone
+        fs::remove_file(&file).unwrap();
+        let names = |filter: TestFilter| -> Vec<String> {
+            collector.query_index(&filter).into_iter().map(|item| item.name).collect()
+        };
+        assert_eq!(names(TestFilter::new(Some("login".to_string()), None)), vec!["test_slow_login", "test_fast_login"]);
+        assert_eq!(names(TestFilter::new(None, Some("slow".to_string()))), vec!["test_slow_login"]);
+        assert_eq!(collector.stats.files_parsed.load(Ordering::Relaxed), 1);
     }
 
     #[test]
-    fn test_extract_markers_pytest_mark() {
+    fn test_utf16_files_are_decoded() {
         let temp_dir = TempDir::new().unwrap();
+        let source = "def test_wide():\n    assert 'é'\n";
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(source.encode_utf16().flat_map(u16::to_le_bytes));
+        fs::write(temp_dir.path().join("test_utf16.py"), bytes).unwrap();
+        fs::write(temp_dir.path().join("test_binary.py"), [0x80, 0x81, 0xFE]).unwrap();
         let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
 
-        let content = r#"
-import pytest
+        let node_ids = collector.filtered_node_ids(&TestFilter::new(None, None));
+        assert_eq!(node_ids, vec!["test_utf16.py::test_wide".to_string()]);
+        let warnings = collector.warnings.read().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Skipping undecodable test file"));
+    }
 
-@pytest.mark.slow
-def test_foo():
-    pass
-"#;
-        let test_file = create_test_file(&temp_dir, "test_markers.py", content);
-        let items = collector.parse_test_file(&test_file).unwrap();
+    #[test]
+    fn test_decode_source_fallbacks() {
+        // Latin-1 only with a coding cookie on one of the first two lines
+        let latin1 = b"#!/usr/bin/env python\n# -*- coding: latin-1 -*-\ndef test_caf\xe9():\n    pass\n";
+        assert_eq!(decode_source(latin1).unwrap().lines().nth(2), Some("def test_café():"));
+        assert!(decode_source(b"# vim: set fileencoding=ISO_8859_1 :\nx = '\xe9'\n").is_some());
+        assert!(decode_source(b"x = '\xe9'\n").is_none());
+        assert!(decode_source(b"# coding: utf-8\nx = '\xe9'\n").is_none());
+        assert!(decode_source(b"x = 1\n# coding: latin-1\ny = '\xe9'\n").is_none());
+
+        // A trailing odd byte makes UTF-16 undecodable
+        let mut utf16: Vec<u8> = vec![0xFF, 0xFE];
+        utf16.extend("x = 1\n".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(decode_source(&utf16).as_deref(), Some("x = 1\n"));
+        utf16.push(b'y');
+        assert!(decode_source(&utf16).is_none());
+    }
 
-        let test_item = items.iter().find(|i| i.name == "test_foo").unwrap();
-        assert_eq!(test_item.markers, vec!["slow"]);
+    #[test]
+    fn test_rewrite_node_ids() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_file(&temp_dir, "test_ids.py", "def test_a():\n    pass\n\nclass TestB:\n    def test_c(self):\n        pass\n");
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        let node_ids = collector.filtered_node_ids(&TestFilter::new(None, None));
+
+        let upper = rewrite_node_ids(node_ids.clone(), |node_id| Ok::<_, String>(node_id.to_uppercase()));
+        assert_eq!(upper.unwrap(), vec!["TEST_IDS.PY::TEST_A", "TEST_IDS.PY::TESTB::TEST_C"]);
+        let failed = rewrite_node_ids(node_ids, |node_id| match node_id.contains("TestB") {
+            true => Err(format!("cannot map {}", node_id)),
+            false => Ok(node_id),
+        });
+        assert_eq!(failed.unwrap_err(), "cannot map test_ids.py::TestB::test_c");
     }
 
     #[test]
-    fn test_extract_markers_multiple() {
+    fn test_infer_coverage_targets() {
         let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("unit")).unwrap();
+        create_test_file(&temp_dir, "test_auth.py", "def test_login():\n    pass\n\nclass TestTokens:\n    def test_refresh(self):\n        pass\n");
+        create_test_file(&temp_dir, "unit/auth_test.py", "def test_hash():\n    pass\n");
+        create_test_file(&temp_dir, "test_billing.py", "def test_invoice():\n    pass\n");
         let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
 
-        let content = r#"
+        let targets = collector.coverage_targets();
+        assert_eq!(targets.keys().collect::<Vec<_>>(), vec!["auth", "billing"]);
+        assert_eq!(
+            targets["auth"],
+            vec!["test_auth.py::TestTokens::test_refresh", "test_auth.py::test_login", "unit/auth_test.py::test_hash"]
+        );
+    }
+
+    #[test]
+    fn test_fixture_only_files_are_excluded() {
+        let temp_dir = TempDir::new().unwrap();
+        let fixtures = r#"
 import pytest
 
-@pytest.mark.slow
-@pytest.mark.integration
-@pytest.mark.smoke
-def test_foo():
-    pass
+@pytest.fixture
+def test_database():
+    return {}
+
+@pytest.fixture(scope="session")
+def test_client():
+    return object()
+
+def make_user():
+    return "alice"
 "#;
-        let test_file = create_test_file(&temp_dir, "test_markers.py", content);
-        let items = collector.parse_test_file(&test_file).unwrap();
+        create_test_file(&temp_dir, "test_fixtures.py", fixtures);
+        create_test_file(&temp_dir, "test_real.py", "import pytest\n\n@pytest.fixture\ndef test_value():\n    return 1\n\ndef test_uses(test_value):\n    pass\n");
+        let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
 
-        let test_item = items.iter().find(|i| i.name == "test_foo").unwrap();
-        assert!(test_item.markers.contains(&"slow".to_string()));
-        assert!(test_item.markers.contains(&"integration".to_string()));
-        assert!(test_item.markers.contains(&"smoke".to_string()));
+        let files = collector.collect_filtered(&TestFilter::new(None, None));
+        assert_eq!(files.len(), 1);
+        let names: Vec<&str> = files[0].test_items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["test_uses"]);
     }
 
     #[test]
-    fn test_line_numbers_are_correct() {
+    fn test_item_objects_expose_attributes() {
         let temp_dir = TempDir::new().unwrap();
+        let content = "import pytest\n\nclass TestAPI:\n    @pytest.mark.slow\n    def test_get(self, client):\n        pass\n";
+        create_test_file(&temp_dir, "test_api.py", content);
         let collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
 
+        let items = collector.item_objects(&TestFilter::new(None, Some("slow".to_string())));
+        assert_eq!(items.len(), 1);
+        let item = &items[0];
+        assert_eq!(item.name(), "test_get");
+        assert_eq!(item.markers(), vec!["slow"]);
+        assert_eq!(item.class_name(), Some("TestAPI"));
+        assert_eq!(item.item_type(), "Method");
+        assert_eq!(item.requested_fixtures(), vec!["client"]);
+        assert_eq!(item.__repr__(), "<CollectedItem test_api.py::TestAPI::test_get>");
+    }
+
+    #[test]
+    fn test_conflicting_markers_warn() {
+        let temp_dir = TempDir::new().unwrap();
         let content = r#"
-# Line 1
-# Line 2
-def test_one():  # Line 3
+import pytest
+
+pytestmark = pytest.mark.run
+
+@pytest.mark.skip
+def test_both():
     pass
 
-# Line 6
-def test_two():  # Line 7
+def test_run_only():
     pass
 "#;
-        let test_file = create_test_file(&temp_dir, "test_lines.py", content);
-        let items = collector.parse_test_file(&test_file).unwrap();
-
-        let test_one = items.iter().find(|i| i.name == "test_one").unwrap();
-        let test_two = items.iter().find(|i| i.name == "test_two").unwrap();
-
-        // Line numbers start at 0 in the AST, but we report 1-indexed
-        assert!(test_one.line_number > 0);
-        assert!(test_two.line_number > test_one.line_number);
+        create_test_file(&temp_dir, "test_conflicts.py", content);
+        let mut collector = FastCollector::new(temp_dir.path().to_str().unwrap().to_string());
+        collector.conflicting_markers = vec![("skip".to_string(), "run".to_string())];
+        collector.collect_filtered(&TestFilter::new(None, None));
+
+        assert_eq!(
+            *collector.warnings.read().unwrap(),
+            vec!["test_conflicts.py::test_both: conflicting markers 'skip' and 'run'"]
+        );
     }
 }
@@ -0,0 +1,169 @@
+"""
+Tests for the Rust FastCollector entry points that take or return Python objects.
+
+Skipped when the Rust extension is not built.
+"""
+
+import io
+import json
+
+import pytest
+
+from pytest_fastcollect import FastCollector
+
+pytestmark = pytest.mark.skipif(FastCollector is None, reason="Rust extension not built")
+
+SOURCE = """
+def test_virtual():
+    pass
+
+class TestGroup:
+    def test_member(self):
+        pass
+"""
+
+
+class TestCollectReader:
+    """Test collection from readable objects (collect_reader)."""
+
+    def test_string_io(self, tmp_path):
+        """Test that source read from io.StringIO is collected under the virtual path."""
+        collector = FastCollector(str(tmp_path))
+
+        data = json.loads(collector.collect_reader(io.StringIO(SOURCE), "pkg/test_virtual.py"))
+
+        assert data["path"] == "pkg/test_virtual.py"
+        assert data["mtime"] == 0.0
+        names = [item["name"] for item in data["test_items"]]
+        assert names == ["test_virtual", "TestGroup", "test_member"]
+        assert all(item["file_path"] == "pkg/test_virtual.py" for item in data["test_items"])
+
+    def test_bytes_io(self, tmp_path):
+        """Test that UTF-8 bytes returned by read() are accepted."""
+        collector = FastCollector(str(tmp_path))
+
+        data = json.loads(collector.collect_reader(io.BytesIO(SOURCE.encode()), "test_bytes.py"))
+
+        assert [item["name"] for item in data["test_items"]] == ["test_virtual", "TestGroup", "test_member"]
+
+    def test_invalid_utf8_raises(self, tmp_path):
+        """Test that undecodable bytes raise ValueError naming the virtual path."""
+        collector = FastCollector(str(tmp_path))
+
+        with pytest.raises(ValueError, match="test_latin1.py"):
+            collector.collect_reader(io.BytesIO(b"# caf\xe9\n"), "test_latin1.py")
+
+
+class TestNodeIdRewriter:
+    """Test rewriting the node ids returned by collect_node_ids."""
+
+    def test_uppercase_rewriter(self, tmp_path):
+        """Test that collect_node_ids returns the ids produced by the callable."""
+        (tmp_path / "test_ids.py").write_text(SOURCE)
+        collector = FastCollector(str(tmp_path))
+        collector.set_node_id_rewriter(str.upper)
+
+        assert collector.collect_node_ids() == [
+            "TEST_IDS.PY::TEST_VIRTUAL",
+            "TEST_IDS.PY::TESTGROUP::TEST_MEMBER",
+        ]
+
+    def test_removing_rewriter(self, tmp_path):
+        """Test that None restores the generated ids."""
+        (tmp_path / "test_ids.py").write_text(SOURCE)
+        collector = FastCollector(str(tmp_path))
+        collector.set_node_id_rewriter(str.upper)
+        collector.set_node_id_rewriter(None)
+
+        assert collector.collect_node_ids() == [
+            "test_ids.py::test_virtual",
+            "test_ids.py::TestGroup::test_member",
+        ]
+
+    def test_rewriter_errors_propagate(self, tmp_path):
+        """Test that an exception raised by the callable reaches the caller."""
+        (tmp_path / "test_ids.py").write_text(SOURCE)
+        collector = FastCollector(str(tmp_path))
+
+        def reject(node_id):
+            raise RuntimeError(f"rejected {node_id}")
+
+        collector.set_node_id_rewriter(reject)
+        with pytest.raises(RuntimeError, match="rejected test_ids.py::test_virtual"):
+            collector.collect_node_ids()
+
+
+class TestCollectItems:
+    """Test the CollectedItem objects returned by collect_items."""
+
+    def test_item_attributes(self, tmp_path):
+        """Test reading item.name and item.markers from the returned objects."""
+        (tmp_path / "test_items.py").write_text(
+            "import pytest\n\n@pytest.mark.slow\n@pytest.mark.smoke\ndef test_marked():\n    pass\n"
+        )
+        collector = FastCollector(str(tmp_path))
+
+        items = collector.collect_items()
+
+        assert len(items) == 1
+        item = items[0]
+        assert type(item).__name__ == "CollectedItem"
+        assert item.name == "test_marked"
+        assert sorted(item.markers) == ["slow", "smoke"]
+        assert item.node_id == "test_items.py::test_marked"
+        assert item.type == "Function"
+
+    def test_marker_filter(self, tmp_path):
+        """Test that marker_expr selects the returned items."""
+        (tmp_path / "test_items.py").write_text(
+            "import pytest\n\n@pytest.mark.slow\ndef test_slow():\n    pass\n\ndef test_fast():\n    pass\n"
+        )
+        collector = FastCollector(str(tmp_path))
+
+        assert [item.name for item in collector.collect_items(marker_expr="slow")] == ["test_slow"]
+
+
+class TestTimeouts:
+    """Test the pytest-timeout values reported for items."""
+
+    def test_timeout_reported_only_when_set(self, tmp_path):
+        """Test that get_timeout() and the item dicts carry literal timeout values."""
+        (tmp_path / "test_timeout.py").write_text(
+            "import pytest\n\n@pytest.mark.timeout(30)\ndef test_bounded():\n    pass\n\n"
+            "def test_unbounded():\n    pass\n"
+        )
+        collector = FastCollector(str(tmp_path))
+
+        items = collector.collect_items()
+        assert [item.get_timeout() for item in items] == [30.0, None]
+
+        data = collector.collect()
+        bounded, unbounded = next(iter(data.values()))
+        assert bounded["timeout"] == 30.0
+        assert "timeout" not in unbounded
+
+
+class TestUnknownParametrizeBehavior:
+    """Test set_unknown_parametrize_behavior argument handling."""
+
+    SOURCE = (
+        "import pytest\n\nCASES = load_cases()\n\n"
+        "@pytest.mark.parametrize('case', CASES)\ndef test_dynamic(case):\n    pass\n"
+    )
+
+    @pytest.mark.parametrize("behavior, expected", [("none", None), ("one", 1), (4, 4), ("4", 4)])
+    def test_valid_behaviors(self, tmp_path, behavior, expected):
+        """Test that names and counts set the reported parametrize count."""
+        (tmp_path / "test_dynamic.py").write_text(self.SOURCE)
+        collector = FastCollector(str(tmp_path))
+        collector.set_unknown_parametrize_behavior(behavior)
+
+        (item,) = collector.collect_items()
+        assert item.parametrize_count == expected
+
+    def test_invalid_behavior_raises(self, tmp_path):
+        """Test that an unknown name raises ValueError."""
+        collector = FastCollector(str(tmp_path))
+
+        with pytest.raises(ValueError, match="many"):
+            collector.set_unknown_parametrize_behavior("many")